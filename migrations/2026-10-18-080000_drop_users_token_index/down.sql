@@ -0,0 +1 @@
+CREATE INDEX users_token_idx ON users (token);
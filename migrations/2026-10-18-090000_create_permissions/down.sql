@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS permissions;
+
+DELETE FROM user_groups WHERE code = 'guest';
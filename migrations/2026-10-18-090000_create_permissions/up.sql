@@ -0,0 +1,48 @@
+INSERT INTO user_groups (name, code) VALUES ('Гости', 'guest');
+
+CREATE TABLE IF NOT EXISTS permissions (
+    id serial NOT NULL PRIMARY KEY,
+    group_id int NOT NULL REFERENCES user_groups(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    action text NOT NULL,
+    UNIQUE (group_id, action)
+);
+
+INSERT INTO permissions (group_id, action)
+SELECT g.id, a.action FROM user_groups AS g
+CROSS JOIN (VALUES
+    ('mandela.getOne'),
+    ('mandela.getAll'),
+    ('user.create'),
+    ('user.auth'),
+    ('user.getOne'),
+    ('comment.getAll'),
+    ('search.getById'),
+    ('search.getByContent'),
+    ('rating.getMandels'),
+    ('rating.getUsers')
+) AS a(action);
+
+INSERT INTO permissions (group_id, action)
+SELECT g.id, a.action FROM user_groups AS g
+CROSS JOIN (VALUES
+    ('mandela.create'),
+    ('mandela.update'),
+    ('mandela.mark'),
+    ('mandela.vote'),
+    ('user.update'),
+    ('user.changePassword'),
+    ('comment.create'),
+    ('comment.update'),
+    ('comment.delete')
+) AS a(action)
+WHERE g.code <> 'guest';
+
+INSERT INTO permissions (group_id, action)
+SELECT g.id, a.action FROM user_groups AS g
+CROSS JOIN (VALUES
+    ('mandela.delete'),
+    ('user.update.any'),
+    ('permission.getAll'),
+    ('permission.update')
+) AS a(action)
+WHERE g.code = 'admin';
@@ -0,0 +1,10 @@
+DELETE FROM permissions WHERE action IN (
+    'mandela.update.any',
+    'mandela.delete.any',
+    'comment.update.any',
+    'comment.delete.any'
+);
+
+DELETE FROM permissions
+WHERE action = 'mandela.delete'
+AND group_id IN (SELECT id FROM user_groups WHERE code NOT IN ('guest', 'admin'));
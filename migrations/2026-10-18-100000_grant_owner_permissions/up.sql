@@ -0,0 +1,13 @@
+INSERT INTO permissions (group_id, action)
+SELECT g.id, 'mandela.delete' FROM user_groups AS g
+WHERE g.code NOT IN ('guest', 'admin');
+
+INSERT INTO permissions (group_id, action)
+SELECT g.id, a.action FROM user_groups AS g
+CROSS JOIN (VALUES
+    ('mandela.update.any'),
+    ('mandela.delete.any'),
+    ('comment.update.any'),
+    ('comment.delete.any')
+) AS a(action)
+WHERE g.code = 'admin';
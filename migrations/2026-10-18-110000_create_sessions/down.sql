@@ -0,0 +1,3 @@
+DELETE FROM permissions WHERE action IN ('user.logout', 'user.logoutAll', 'user.getSessions');
+
+DROP TABLE IF EXISTS sessions;
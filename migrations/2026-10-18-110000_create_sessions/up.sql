@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS sessions (
+    id serial NOT NULL PRIMARY KEY,
+    user_id int NOT NULL REFERENCES users(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    token text UNIQUE NOT NULL,
+    user_agent text,
+    create_ts timestamptz NOT NULL DEFAULT now(),
+    expire_ts timestamptz NOT NULL
+);
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
+
+INSERT INTO permissions (group_id, action)
+SELECT g.id, a.action FROM user_groups AS g
+CROSS JOIN (VALUES
+    ('user.logout'),
+    ('user.logoutAll'),
+    ('user.getSessions')
+) AS a(action)
+WHERE g.code <> 'guest';
@@ -0,0 +1,3 @@
+-- Plain tokens can't be kept, so existing sessions are closed
+DELETE FROM sessions;
+ALTER TABLE sessions RENAME COLUMN token TO token_hash;
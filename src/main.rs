@@ -2,19 +2,26 @@
 extern crate diesel_migrations;
 use log::info;
 use ocean::app;
+use ocean::config;
 use ocean::db;
+use ocean::logger;
 
 embed_migrations!("migrations");
 
 #[tokio::main]
 async fn main() {
-    env_logger::builder().format_timestamp(None).init();
+    logger::init();
+    logger::configure(&config::get().log);
 
     info!("Ocean started");
 
-    let db = db::Db::new();
+    let db = db::Db::new().unwrap_or_else(|e| panic!("Database connection error: {}", e));
     embedded_migrations::run_with_output(&db.conn, &mut std::io::stdout()).unwrap();
+    db::set_migrated(&db.conn).unwrap_or_else(|e| panic!("Migrations check error: {}", e));
 
     let app = app::App::new();
     app.start().await;
+
+    // Blocking tasks left after the shutdown deadline are not waited for
+    std::process::exit(0);
 }
@@ -0,0 +1,121 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::time::{delay_for, Delay};
+
+// Accepted TCP connection. While no request is handled, the client has
+// read timeout to send complete request headers, otherwise the connection
+// is closed. It protects from idle and slowly sending clients.
+pub struct Connection {
+    stream: TcpStream,
+    remote_addr: SocketAddr,
+    state: Arc<State>,
+    read_timeout: Duration,
+    // Deadline for the request following the given number of requests
+    deadline: Option<(usize, Delay)>,
+    _permit: OwnedSemaphorePermit,
+}
+
+#[derive(Default)]
+pub struct State {
+    busy: AtomicBool,
+    requests: AtomicUsize,
+}
+
+// Marks the connection busy while a request is handled
+pub struct Busy(Arc<State>);
+
+impl Connection {
+    pub fn new(
+        stream: TcpStream,
+        remote_addr: SocketAddr,
+        read_timeout: Duration,
+        permit: OwnedSemaphorePermit,
+    ) -> Self {
+        Connection {
+            stream,
+            remote_addr,
+            state: Arc::new(State::default()),
+            read_timeout,
+            deadline: None,
+            _permit: permit,
+        }
+    }
+
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    pub fn state(&self) -> Arc<State> {
+        self.state.clone()
+    }
+}
+
+impl Busy {
+    pub fn new(state: Arc<State>) -> Self {
+        state.requests.fetch_add(1, Ordering::Relaxed);
+        state.busy.store(true, Ordering::Relaxed);
+        Busy(state)
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.0.busy.store(false, Ordering::Relaxed);
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        // Reading of the body is limited by the request handler
+        if self.state.busy.load(Ordering::Relaxed) {
+            return Pin::new(&mut self.stream).poll_read(cx, buf);
+        }
+
+        // Not reset by received bytes, so slow clients are not waited for
+        let requests = self.state.requests.load(Ordering::Relaxed);
+        if !matches!(&self.deadline, Some((n, _)) if *n == requests) {
+            self.deadline = Some((requests, delay_for(self.read_timeout)));
+        }
+
+        let (_, deadline) = self.deadline.as_mut().unwrap();
+        if Pin::new(deadline).poll(cx).is_ready() {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request headers read timeout",
+            )));
+        }
+
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
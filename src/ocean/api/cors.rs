@@ -0,0 +1,103 @@
+use crate::config;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+
+// OPTIONS /api: allowed origins get the methods and headers they may use
+pub fn preflight(req: &Request<Body>) -> Response<Body> {
+    let config = config::get();
+    let cors = &config.cors;
+    let headers = req.headers();
+
+    let method_allowed = headers
+        .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|m| cors.allow_methods.iter().any(|a| a == m));
+
+    let headers_allowed = headers
+        .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|h| {
+            h.split(',')
+                .map(|n| n.trim())
+                .filter(|n| !n.is_empty())
+                .all(|n| cors.allow_headers.iter().any(|a| a.eq_ignore_ascii_case(n)))
+        });
+
+    let status = if method_allowed && headers_allowed && allow_origin(headers).is_some() {
+        StatusCode::NO_CONTENT
+    } else {
+        info!("CORS preflight is rejected");
+        StatusCode::FORBIDDEN
+    };
+
+    let mut response = Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap();
+
+    if status != StatusCode::NO_CONTENT {
+        set_vary(&mut response);
+        return response;
+    }
+
+    set_origin(headers, &mut response);
+    let response_headers = response.headers_mut();
+    // Validated on config load
+    if let Ok(v) = HeaderValue::from_str(&cors.allow_methods.join(", ")) {
+        response_headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, v);
+    }
+    if let Ok(v) = HeaderValue::from_str(&cors.allow_headers.join(", ")) {
+        response_headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, v);
+    }
+    response_headers.insert(header::ACCESS_CONTROL_MAX_AGE, cors.max_age.into());
+
+    response
+}
+
+// Sets CORS headers of a response to a request with the given headers,
+// only Vary is set if the origin is not allowed
+pub fn set_origin(headers: &HeaderMap, response: &mut Response<Body>) {
+    set_vary(response);
+
+    let allow_origin = match allow_origin(headers) {
+        Some(o) => o,
+        None => return,
+    };
+
+    let config = config::get();
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+
+    if config.cors.allow_credentials {
+        response_headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+}
+
+// Responses differ by origin unless any origin is allowed, so caches
+// must not replay them to other origins
+fn set_vary(response: &mut Response<Body>) {
+    if !config::get().cors.any_origin() {
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("Origin"));
+    }
+}
+
+// Credentials can't be allowed with "*", it is checked on config load
+fn allow_origin(headers: &HeaderMap) -> Option<HeaderValue> {
+    let config = config::get();
+    let cors = &config.cors;
+
+    if cors.any_origin() {
+        return Some(HeaderValue::from_static("*"));
+    }
+
+    headers
+        .get(header::ORIGIN)
+        .filter(|o| cors.allow_origins.iter().any(|a| a == *o))
+        .cloned()
+}
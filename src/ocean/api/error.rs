@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use super::locale::Locale;
+use diesel::result::DatabaseErrorKind;
+use serde_json::json;
 use std::error;
 use std::fmt;
 
@@ -11,37 +13,102 @@ pub const METHOD_NOT_FOUND: ErrorCode = 3;
 pub const PARAMETER_NOT_FOUND: ErrorCode = 4;
 pub const INTERNAL_SERVER_ERROR: ErrorCode = 5;
 pub const INVALID_PARAMETER: ErrorCode = 6;
+pub const FORBIDDEN: ErrorCode = 7;
+pub const INVALID_REQUEST: ErrorCode = 8;
+pub const SERVICE_UNAVAILABLE: ErrorCode = 9;
+pub const TIMEOUT: ErrorCode = 10;
+pub const NOT_FOUND: ErrorCode = 11;
+pub const CONFLICT: ErrorCode = 12;
+pub const RATE_LIMITED: ErrorCode = 13;
 
 // User (100..199)
 pub const WRONG_USER_PASSWORD: ErrorCode = 100;
+pub const NOT_AUTHORIZED: ErrorCode = 101;
+pub const PERMISSION_DENIED: ErrorCode = 102;
 
-lazy_static! {
-    static ref ERROR_MESSAGES: HashMap<ErrorCode, &'static str> = {
-        let mut m = HashMap::new();
-        m.insert(PARSE_ERROR, "Parse error");
-        m.insert(CONTROLLER_NOT_FOUND, "Controller not found");
-        m.insert(METHOD_NOT_FOUND, "Method not found");
-        m.insert(PARAMETER_NOT_FOUND, "Parameter not found");
-        m.insert(INTERNAL_SERVER_ERROR, "Internal server error");
-        m.insert(INVALID_PARAMETER, "Invalid parameter");
-
-        m.insert(WRONG_USER_PASSWORD, "Wrong user password");
-        m
-    };
+// Typed failures of request handling, each one has its own error code
+#[derive(Debug)]
+pub enum Failure {
+    // Name of the missing record or parameter
+    NotFound(Option<String>),
+    // Action which is not permitted for the caller
+    PermissionDenied(Option<String>),
+    // Record which may be changed only by its author or a moderator
+    Forbidden,
+    // Name of the conflicting field
+    Conflict(Option<String>),
+    // Seconds to wait before the next request
+    RateLimited(u64),
+    // List of field errors
+    Validation(serde_json::Value),
+    Unauthenticated,
+    WrongPassword,
 }
 
-#[derive(Debug)]
+impl Failure {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Failure::NotFound(_) => NOT_FOUND,
+            Failure::PermissionDenied(_) => PERMISSION_DENIED,
+            Failure::Forbidden => FORBIDDEN,
+            Failure::Conflict(_) => CONFLICT,
+            Failure::RateLimited(_) => RATE_LIMITED,
+            Failure::Validation(_) => INVALID_PARAMETER,
+            Failure::Unauthenticated => NOT_AUTHORIZED,
+            Failure::WrongPassword => WRONG_USER_PASSWORD,
+        }
+    }
+
+    // Database errors caused by the request itself, the rest are internal errors.
+    // Constraint names are not exposed to clients.
+    pub fn from_diesel(err: &diesel::result::Error) -> Option<Failure> {
+        use diesel::result::Error::{DatabaseError, NotFound};
+
+        match err {
+            NotFound => Some(Failure::NotFound(None)),
+            // A referenced record does not exist
+            DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+                Some(Failure::NotFound(None))
+            }
+            DatabaseError(DatabaseErrorKind::UniqueViolation, _) => Some(Failure::Conflict(None)),
+            _ => None,
+        }
+    }
+}
+
+impl From<Failure> for Error {
+    fn from(failure: Failure) -> Self {
+        let code = failure.code();
+        let data = match failure {
+            Failure::NotFound(name) | Failure::Conflict(name) | Failure::PermissionDenied(name) => {
+                name.map(serde_json::Value::from)
+            }
+            Failure::RateLimited(secs) => Some(json!({ "retry_after": secs })),
+            Failure::Validation(fields) => Some(fields),
+            Failure::Forbidden | Failure::Unauthenticated | Failure::WrongPassword => None,
+        };
+        Error::new(code, data)
+    }
+}
+
+impl From<Failure> for Box<dyn error::Error> {
+    fn from(failure: Failure) -> Self {
+        Box::new(Error::from(failure))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Error {
     code: ErrorCode,
     message: String,
-    data: Option<String>,
+    data: Option<serde_json::Value>,
 }
 
 impl Error {
-    pub fn new(code: ErrorCode, data: Option<String>) -> Self {
+    pub fn new(code: ErrorCode, data: Option<serde_json::Value>) -> Self {
         Error {
             code,
-            message: (*ERROR_MESSAGES.get(&code).unwrap()).to_string(),
+            message: Locale::default().message(code),
             data,
         }
     }
@@ -50,13 +117,37 @@ impl Error {
         self.code
     }
 
+    // Message in the default language
     pub fn message(&self) -> String {
         self.message.clone()
     }
 
-    pub fn data(&self) -> Option<String> {
+    pub fn localized_message(&self, locale: &Locale) -> String {
+        locale.message(self.code)
+    }
+
+    pub fn data(&self) -> Option<serde_json::Value> {
         self.data.clone()
     }
+
+    // Data with reasons of field errors translated, other data is kept as is
+    pub fn localized_data(&self, locale: &Locale) -> Option<serde_json::Value> {
+        match (&self.data, self.code) {
+            (Some(serde_json::Value::Array(fields)), INVALID_PARAMETER) => Some(
+                fields
+                    .iter()
+                    .map(|f| {
+                        let reason = f["reason"].as_str().unwrap_or_default();
+                        json!({
+                            "field": f["field"],
+                            "reason": locale.reason(reason, f.get("args")),
+                        })
+                    })
+                    .collect(),
+            ),
+            _ => self.data(),
+        }
+    }
 }
 
 impl error::Error for Error {}
@@ -75,6 +166,20 @@ pub fn make_error(code: ErrorCode) -> Box<dyn error::Error> {
     Box::new(Error::new(code, None))
 }
 
-pub fn make_error_data(code: ErrorCode, data: String) -> Box<dyn error::Error> {
-    Box::new(Error::new(code, Some(data)))
+pub fn make_error_data<T: Into<serde_json::Value>>(
+    code: ErrorCode,
+    data: T,
+) -> Box<dyn error::Error> {
+    Box::new(Error::new(code, Some(data.into())))
+}
+
+// Finds the API error behind an error returned by a request handler
+pub fn find_error(err: &(dyn error::Error + 'static)) -> Option<Error> {
+    if let Some(e) = err.downcast_ref::<Error>() {
+        Some(e.clone())
+    } else {
+        err.downcast_ref::<diesel::result::Error>()
+            .and_then(Failure::from_diesel)
+            .map(Error::from)
+    }
 }
@@ -0,0 +1,107 @@
+use crate::config;
+use crate::db;
+use crate::telegram_bot;
+use diesel::prelude::*;
+use hyper::header;
+use hyper::{Body, Response, StatusCode};
+use log::error;
+use serde_json::json;
+
+// GET /health: the process is up
+pub fn health() -> Response<Body> {
+    json_response(StatusCode::OK, json!({ "status": "ok" }))
+}
+
+// GET /ready: all components are able to serve requests
+pub async fn ready() -> Response<Body> {
+    let components = match tokio::task::spawn_blocking(check_components).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Readiness check error: {}", e);
+            let body = json!({ "status": "not_ready" });
+            return json_response(StatusCode::SERVICE_UNAVAILABLE, body);
+        }
+    };
+
+    let ready = ["database", "migrations", "telegram_bot"]
+        .iter()
+        .all(|c| components[c]["status"] != "down");
+
+    let (status, code) = if ready {
+        ("ready", StatusCode::OK)
+    } else {
+        ("not_ready", StatusCode::SERVICE_UNAVAILABLE)
+    };
+
+    json_response(code, json!({ "status": status, "components": components }))
+}
+
+// Does blocking database calls
+fn check_components() -> serde_json::Value {
+    json!({
+        "database": component_status("database", check_database()),
+        "migrations": component_status("migrations", check_migrations()),
+        "telegram_bot": check_telegram_bot(),
+    })
+}
+
+// Errors may contain database hosts and names, so they are only logged
+fn component_status(
+    name: &str,
+    result: Result<(), Box<dyn std::error::Error>>,
+) -> serde_json::Value {
+    match result {
+        Ok(()) => json!({ "status": "ok" }),
+        Err(e) => {
+            error!("Readiness check: {}: {}", name, e);
+            json!({ "status": "down" })
+        }
+    }
+}
+
+fn check_database() -> Result<(), Box<dyn std::error::Error>> {
+    let db = db::Db::new()?;
+    diesel::sql_query("SELECT 1").execute(&db.conn)?;
+    Ok(())
+}
+
+fn check_migrations() -> Result<(), Box<dyn std::error::Error>> {
+    let db = db::Db::new()?;
+
+    match db::pending_migrations(&db.conn)? {
+        None => Err("migrations are not applied".into()),
+        Some(p) if !p.is_empty() => Err(format!("pending migrations: {}", p.join(", ")).into()),
+        Some(_) => Ok(()),
+    }
+}
+
+// The bot loop is alive while polls are not late for more than one interval
+fn check_telegram_bot() -> serde_json::Value {
+    let config = config::get();
+
+    if !config.telegram_bot.enabled {
+        return json!({ "status": "disabled" });
+    }
+
+    let last_poll = match telegram_bot::last_poll() {
+        Some(t) => t,
+        None => return json!({ "status": "down" }),
+    };
+
+    let deadline = chrono::Duration::seconds(config.telegram_bot.interval * 2);
+    let status = if chrono::Utc::now() - last_poll <= deadline {
+        "ok"
+    } else {
+        "down"
+    };
+
+    json!({ "status": status, "last_poll": last_poll.to_rfc3339() })
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
@@ -0,0 +1,225 @@
+use super::error::ErrorCode;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+// Messages of this language are used when no preferred language has them
+pub const DEFAULT_LANG: &str = "ru";
+
+lazy_static! {
+    static ref CATALOGS: HashMap<&'static str, Catalog> = {
+        let mut m = HashMap::new();
+        m.insert(
+            "en",
+            load_catalog("en", include_str!("../../../locales/en.toml")),
+        );
+        m.insert(
+            "ru",
+            load_catalog("ru", include_str!("../../../locales/ru.toml")),
+        );
+        m
+    };
+}
+
+// TOML keys are strings, so codes are kept as strings too
+#[derive(Deserialize)]
+struct Catalog {
+    errors: HashMap<String, String>,
+    #[serde(default)]
+    validation: HashMap<String, String>,
+}
+
+fn load_catalog(lang: &str, source: &str) -> Catalog {
+    toml::from_str(source).unwrap_or_else(|e| panic!("Locale {} error: {}", lang, e))
+}
+
+// Languages preferred by a client, most preferred first
+#[derive(Clone, Debug, Default)]
+pub struct Locale {
+    langs: Vec<String>,
+}
+
+impl Locale {
+    // Parses Accept-Language header, e.g. "ru-RU,ru;q=0.9,en;q=0.8"
+    pub fn from_accept_language(header: Option<&str>) -> Self {
+        let mut ranges = header
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .next()
+                    .and_then(|q| q.parse::<f32>().ok())
+                    // Malformed values like "NaN" or "2" are ignored
+                    .filter(|q| (0.0..=1.0).contains(q))
+                    .unwrap_or(1.0);
+                if tag.is_empty() || tag == "*" || quality <= 0.0 {
+                    None
+                } else {
+                    Some((tag, quality))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Stable sort keeps the order of ranges with equal quality
+        ranges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut locale = Locale::default();
+        for (tag, _) in ranges {
+            locale.push(tag);
+        }
+        locale
+    }
+
+    // Returns the locale with a language requested explicitly put first
+    pub fn prefer(&self, lang: Option<&str>) -> Self {
+        let mut locale = Locale::default();
+        if let Some(l) = lang {
+            locale.push(l);
+        }
+        for l in &self.langs {
+            locale.push(l);
+        }
+        locale
+    }
+
+    pub fn message(&self, code: ErrorCode) -> String {
+        let key = code.to_string();
+        self.find(|c| c.errors.get(&key))
+            .unwrap_or_else(|| format!("Error {}", code))
+    }
+
+    // Reason of a field error, "{max}" in the text is replaced with args["max"]
+    pub fn reason(&self, key: &str, args: Option<&serde_json::Value>) -> String {
+        let mut text = self
+            .find(|c| c.validation.get(key))
+            .unwrap_or_else(|| key.to_string());
+        if let Some(serde_json::Value::Object(args)) = args {
+            for (name, value) in args {
+                let value = match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                };
+                text = text.replace(&format!("{{{}}}", name), &value);
+            }
+        }
+        text
+    }
+
+    fn find<'a>(&self, get: impl Fn(&'a Catalog) -> Option<&'a String>) -> Option<String> {
+        self.langs
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(DEFAULT_LANG))
+            .filter_map(|l| CATALOGS.get(l))
+            .find_map(get)
+            .cloned()
+    }
+
+    // Only the primary subtag is used, "ru-RU" is the same as "ru"
+    fn push(&mut self, tag: &str) {
+        let lang = tag.split('-').next().unwrap_or_default().to_lowercase();
+        if !lang.is_empty() && !self.langs.contains(&lang) {
+            self.langs.push(lang);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::error::NOT_FOUND;
+    use serde_json::json;
+
+    fn langs(header: Option<&str>) -> Vec<String> {
+        Locale::from_accept_language(header).langs
+    }
+
+    #[test]
+    fn ranges_are_ordered_by_quality() {
+        assert_eq!(
+            langs(Some("en;q=0.5, ru-RU, de;q=0.8")),
+            vec!["ru", "de", "en"]
+        );
+    }
+
+    #[test]
+    fn equal_quality_keeps_header_order() {
+        assert_eq!(langs(Some("de;q=0.9,en;q=0.9")), vec!["de", "en"]);
+    }
+
+    #[test]
+    fn subtags_and_case_are_merged() {
+        assert_eq!(langs(Some("RU-ru,ru;q=0.9,en-GB")), vec!["ru", "en"]);
+    }
+
+    #[test]
+    fn wildcard_empty_and_rejected_ranges_are_skipped() {
+        assert_eq!(langs(Some("*, ,de;q=0,en;q=0.000")), Vec::<String>::new());
+        assert_eq!(langs(None), Vec::<String>::new());
+        assert_eq!(langs(Some("")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn malformed_quality_counts_as_default() {
+        assert_eq!(
+            langs(Some("de;q=abc,en;q=,fr;q=0.5;level=1,es;level=1")),
+            vec!["de", "en", "es", "fr"]
+        );
+        assert_eq!(langs(Some("de;q=0.5,en;q=NaN")), vec!["en", "de"]);
+        assert_eq!(langs(Some("en;q=0.9,de;q=2,fr")), vec!["de", "fr", "en"]);
+        assert_eq!(langs(Some("de;q=-1,en;q=0.9")), vec!["de", "en"]);
+    }
+
+    #[test]
+    fn explicit_language_is_preferred() {
+        let locale = Locale::from_accept_language(Some("en,ru;q=0.5"));
+        assert_eq!(locale.prefer(Some("ru-RU")).langs, vec!["ru", "en"]);
+        assert_eq!(locale.prefer(None).langs, vec!["en", "ru"]);
+    }
+
+    #[test]
+    fn messages_fall_back_to_default_language() {
+        let en = Locale::from_accept_language(Some("en"));
+        assert_eq!(en.message(NOT_FOUND), "Not found");
+
+        let unknown = Locale::from_accept_language(Some("xx"));
+        assert_eq!(
+            unknown.message(NOT_FOUND),
+            Locale::default().message(NOT_FOUND)
+        );
+        assert_eq!(unknown.message(99), "Error 99");
+    }
+
+    #[test]
+    fn reasons_are_formatted_with_args() {
+        let en = Locale::from_accept_language(Some("en"));
+        let args = json!({ "min": 1, "max": 10 });
+        assert_eq!(en.reason("range", Some(&args)), "must be from 1 to 10");
+        assert_eq!(
+            en.reason("malformed", Some(&json!({ "error": "bad" }))),
+            "malformed parameters: bad"
+        );
+        assert_eq!(en.reason("unknown_key", None), "unknown_key");
+    }
+
+    #[test]
+    fn catalogs_have_the_same_keys() {
+        let en = &CATALOGS["en"];
+        let ru = &CATALOGS["ru"];
+        let mut en_keys = en
+            .errors
+            .keys()
+            .chain(en.validation.keys())
+            .collect::<Vec<_>>();
+        let mut ru_keys = ru
+            .errors
+            .keys()
+            .chain(ru.validation.keys())
+            .collect::<Vec<_>>();
+        en_keys.sort();
+        ru_keys.sort();
+        assert_eq!(en_keys, ru_keys);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::config;
+use crate::config::LogMode;
+use crate::json_rpc;
+use crate::logger;
+use log::info;
+use serde_json::json;
+use std::time::Duration;
+
+const MASK: &str = "***";
+
+// Timings of a single call, handler time is spent on the blocking pool
+// including database work, authorization and handler logic. DB time is
+// the wait for a pooled connection and the time of queries.
+pub struct Call<'a> {
+    pub request_id: &'a str,
+    pub method: &'a str,
+    pub user_id: Option<i32>,
+    pub error_code: Option<i32>,
+    pub handler_time: Option<Duration>,
+    pub db_time: Option<Duration>,
+    pub latency: Duration,
+}
+
+pub fn request(request_id: &str, method: &str, value: &serde_json::Value) {
+    let config = config::get();
+
+    let text = match config.log.method_mode(method) {
+        LogMode::Off => return,
+        LogMode::Summary => format!("{}, id: {}", method, id(value)),
+        LogMode::Full => format(value, &config.log),
+    };
+    write("Request", request_id, method, None, text);
+}
+
+pub fn response(request_id: &str, method: &str, user_id: Option<i32>, resp: &json_rpc::Response) {
+    let config = config::get();
+
+    let text = match config.log.method_mode(method) {
+        LogMode::Off => return,
+        LogMode::Summary => match &resp.payload {
+            json_rpc::response::Payload::Result(_) => {
+                format!("{}, id: {}, result", method, resp.id)
+            }
+            json_rpc::response::Payload::Error(e) => {
+                format!("{}, id: {}, error: {}", method, resp.id, e.code)
+            }
+        },
+        LogMode::Full => match serde_json::to_value(resp) {
+            Ok(v) => format(&v, &config.log),
+            Err(e) => format!("{}, serialization error: {}", method, e),
+        },
+    };
+    write("Response", request_id, method, user_id, text);
+}
+
+// Ids are separate fields of JSON entries, so entries can be filtered by them
+fn write(kind: &str, request_id: &str, method: &str, user_id: Option<i32>, text: String) {
+    if logger::is_json() {
+        let fields = json!({
+            "message": kind,
+            "request_id": request_id,
+            "method": method,
+            "user_id": user_id,
+            "body": text,
+        });
+        info!(target: logger::FIELDS_TARGET, "{}", fields);
+    } else {
+        info!("{} {}: {}", kind, request_id, text);
+    }
+}
+
+pub fn call(call: &Call) {
+    if config::get().log.method_mode(call.method) == LogMode::Off {
+        return;
+    }
+
+    let handler_time_ms = call.handler_time.map(|t| t.as_millis() as u64);
+    let db_time_ms = call.db_time.map(|t| t.as_millis() as u64);
+    let latency_ms = call.latency.as_millis() as u64;
+
+    if logger::is_json() {
+        let fields = json!({
+            "message": "Call",
+            "request_id": call.request_id,
+            "method": call.method,
+            "user_id": call.user_id,
+            "error_code": call.error_code,
+            "handler_time_ms": handler_time_ms,
+            "db_time_ms": db_time_ms,
+            "latency_ms": latency_ms,
+        });
+        info!(target: logger::FIELDS_TARGET, "{}", fields);
+    } else {
+        let show = |v: Option<String>| v.unwrap_or_else(|| "none".to_string());
+        info!(
+            "Call {}: {}, user id: {}, error: {}, handler time: {}, DB time: {}, latency: {} ms",
+            call.request_id,
+            call.method,
+            show(call.user_id.map(|i| i.to_string())),
+            show(call.error_code.map(|c| c.to_string())),
+            show(handler_time_ms.map(|t| format!("{} ms", t))),
+            show(db_time_ms.map(|t| format!("{} ms", t))),
+            latency_ms
+        );
+    }
+}
+
+fn id(value: &serde_json::Value) -> String {
+    match value.get("id") {
+        Some(i) => i.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+fn format(value: &serde_json::Value, log: &config::Log) -> String {
+    let mut value = value.clone();
+    redact(&mut value, &log.sensitive_fields);
+    truncate(value.to_string(), log.max_body_length)
+}
+
+// Masks values of sensitive fields at any depth, names are case insensitive
+fn redact(value: &mut serde_json::Value, fields: &[String]) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                if fields.iter().any(|f| f.eq_ignore_ascii_case(k)) {
+                    *v = serde_json::Value::from(MASK);
+                } else {
+                    redact(v, fields);
+                }
+            }
+        }
+        serde_json::Value::Array(list) => {
+            for v in list {
+                redact(v, fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn truncate(mut body: String, max_length: usize) -> String {
+    if body.len() <= max_length {
+        return body;
+    }
+
+    let length = body.len();
+    let mut end = max_length;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    body.truncate(end);
+    format!("{}... ({} bytes)", body, length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields() -> Vec<String> {
+        vec!["token".to_string(), "Password".to_string()]
+    }
+
+    #[test]
+    fn nested_secrets_are_masked() {
+        let mut value = json!({
+            "method": "user.auth",
+            "params": {
+                "TOKEN": "secret",
+                "user": { "login": "a", "password": { "old": "x", "new": "y" } },
+                "sessions": [{ "token": "s1" }, { "id": 1 }],
+            },
+        });
+        redact(&mut value, &fields());
+        assert_eq!(
+            value,
+            json!({
+                "method": "user.auth",
+                "params": {
+                    "TOKEN": MASK,
+                    "user": { "login": "a", "password": MASK },
+                    "sessions": [{ "token": MASK }, { "id": 1 }],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn values_are_not_matched() {
+        let mut value = json!(["token", { "name": "password" }]);
+        redact(&mut value, &fields());
+        assert_eq!(value, json!(["token", { "name": "password" }]));
+    }
+
+    #[test]
+    fn short_body_is_kept() {
+        assert_eq!(truncate("abc".to_string(), 3), "abc");
+    }
+
+    #[test]
+    fn long_body_is_cut() {
+        assert_eq!(truncate("abcdef".to_string(), 3), "abc... (6 bytes)");
+        assert_eq!(truncate("abc".to_string(), 0), "... (3 bytes)");
+    }
+
+    #[test]
+    fn multibyte_chars_are_not_split() {
+        // Each of these letters takes 2 bytes
+        assert_eq!(truncate("абв".to_string(), 3), "а... (6 bytes)");
+        assert_eq!(truncate("абв".to_string(), 4), "аб... (6 bytes)");
+        assert_eq!(truncate("🦀🦀".to_string(), 3), "... (8 bytes)");
+    }
+}
@@ -1,5 +1,13 @@
+pub mod connection;
+pub mod cors;
 pub mod error;
+pub mod health;
+pub mod locale;
+pub mod logging;
+pub mod rate_limit;
 pub mod router;
 pub mod server;
+pub mod session;
 
-pub use error::{make_error, make_error_data, Error};
+pub use error::{make_error, make_error_data, Error, Failure};
+pub use locale::Locale;
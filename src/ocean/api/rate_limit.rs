@@ -0,0 +1,300 @@
+use crate::api::Failure;
+use crate::config;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::Instant;
+
+// The least recently used buckets are evicted above this number
+const MAX_BUCKETS: usize = 100_000;
+
+lazy_static! {
+    static ref LIMITER: Mutex<Limiter> = Mutex::new(Limiter::default());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Caller {
+    User(i32),
+    Ip(IpAddr),
+}
+
+impl Caller {
+    // IPv6 clients usually get a whole /64 network, so they are limited by it
+    pub fn ip(ip: IpAddr) -> Self {
+        let ip = match ip {
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => IpAddr::V4(v4),
+                None => {
+                    let s = v6.segments();
+                    IpAddr::V6(Ipv6Addr::new(s[0], s[1], s[2], s[3], 0, 0, 0, 0))
+                }
+            },
+            v4 => v4,
+        };
+        Caller::Ip(ip)
+    }
+}
+
+type Key = (String, Caller);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    // Position in the order of use
+    seq: u64,
+}
+
+impl Bucket {
+    fn refill(&mut self, rule: &config::Bucket, now: Instant) {
+        let rate = rule.per_minute as f64 / 60.0;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rule.burst as f64);
+        self.updated = now;
+    }
+
+    // Fails with seconds to wait until the next token
+    fn take(&mut self, rule: &config::Bucket) -> Result<(), u64> {
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let rate = rule.per_minute as f64 / 60.0;
+        let retry_after = ((1.0 - self.tokens) / rate).ceil() as u64;
+        Err(retry_after.max(1))
+    }
+}
+
+#[derive(Default)]
+struct Limiter {
+    buckets: HashMap<Key, Bucket>,
+    // Keys by order of use, the least recently used first
+    order: BTreeMap<u64, Key>,
+    seq: u64,
+}
+
+impl Limiter {
+    fn check(
+        &mut self,
+        rule: &config::Bucket,
+        key: Key,
+        now: Instant,
+        max_buckets: usize,
+    ) -> Result<(), u64> {
+        self.seq += 1;
+        let seq = self.seq;
+
+        if let Some(b) = self.buckets.get_mut(&key) {
+            self.order.remove(&b.seq);
+            b.seq = seq;
+        } else {
+            while self.buckets.len() >= max_buckets {
+                self.evict_oldest();
+            }
+            self.buckets.insert(
+                key.clone(),
+                Bucket {
+                    tokens: rule.burst as f64,
+                    updated: now,
+                    seq,
+                },
+            );
+        }
+        self.order.insert(seq, key.clone());
+
+        let bucket = self.buckets.get_mut(&key).unwrap();
+        bucket.refill(rule, now);
+        bucket.take(rule)
+    }
+
+    fn evict_oldest(&mut self) {
+        let seq = match self.order.keys().next() {
+            Some(s) => *s,
+            None => return,
+        };
+        if let Some(key) = self.order.remove(&seq) {
+            self.buckets.remove(&key);
+        }
+    }
+}
+
+// Takes a token of the caller's bucket for the method
+pub fn check(method: &str, caller: Caller) -> Result<(), Failure> {
+    let config = config::get();
+    let rule = match config.rate_limit.methods.get(method) {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+
+    LIMITER
+        .lock()
+        .unwrap()
+        .check(
+            rule,
+            (method.to_string(), caller),
+            Instant::now(),
+            MAX_BUCKETS,
+        )
+        .map_err(Failure::RateLimited)
+}
+
+// Addresses of trusted proxies are skipped from the end of X-Forwarded-For,
+// the first address they got the request from is the client
+pub fn client_ip(peer: IpAddr, forwarded_for: &[&str], trusted: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+
+    let hops = forwarded_for
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(|a| a.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+
+    for hop in hops.into_iter().rev() {
+        match hop {
+            Some(ip) if trusted.contains(&client) => client = ip,
+            _ => break,
+        }
+    }
+
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn rule(burst: u32, per_minute: u32) -> config::Bucket {
+        config::Bucket { burst, per_minute }
+    }
+
+    fn key(user_id: i32) -> Key {
+        ("mandela.create".to_string(), Caller::User(user_id))
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn burst_is_spent_then_retry_after_is_reported() {
+        let mut limiter = Limiter::default();
+        let rule = rule(2, 6);
+        let now = Instant::now();
+
+        assert_eq!(limiter.check(&rule, key(1), now, 10), Ok(()));
+        assert_eq!(limiter.check(&rule, key(1), now, 10), Ok(()));
+        // 6 per minute is a token every 10 seconds
+        assert_eq!(limiter.check(&rule, key(1), now, 10), Err(10));
+        // Other callers have their own buckets
+        assert_eq!(limiter.check(&rule, key(2), now, 10), Ok(()));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let mut limiter = Limiter::default();
+        let rule = rule(1, 6);
+        let now = Instant::now();
+
+        assert_eq!(limiter.check(&rule, key(1), now, 10), Ok(()));
+        let later = now + Duration::from_secs(4);
+        assert_eq!(limiter.check(&rule, key(1), later, 10), Err(6));
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.check(&rule, key(1), later, 10), Ok(()));
+        // Refill is capped by burst
+        let later = later + Duration::from_secs(3600);
+        assert_eq!(limiter.check(&rule, key(1), later, 10), Ok(()));
+        assert_eq!(limiter.check(&rule, key(1), later, 10), Err(10));
+    }
+
+    #[test]
+    fn retry_after_is_at_least_a_second() {
+        let rule = rule(1, 600);
+        let mut bucket = Bucket {
+            tokens: 0.95,
+            updated: Instant::now(),
+            seq: 0,
+        };
+        assert_eq!(bucket.take(&rule), Err(1));
+    }
+
+    #[test]
+    fn least_recently_used_bucket_is_evicted() {
+        let mut limiter = Limiter::default();
+        let rule = rule(1, 1);
+        let now = Instant::now();
+
+        assert!(limiter.check(&rule, key(1), now, 2).is_ok());
+        assert!(limiter.check(&rule, key(2), now, 2).is_ok());
+        // Using the first bucket makes the second one the oldest
+        assert!(limiter.check(&rule, key(1), now, 2).is_err());
+        assert!(limiter.check(&rule, key(3), now, 2).is_ok());
+
+        assert_eq!(limiter.buckets.len(), 2);
+        assert_eq!(limiter.order.len(), 2);
+        assert!(!limiter.buckets.contains_key(&key(2)));
+        // An evicted caller starts with a full bucket again
+        assert!(limiter.check(&rule, key(2), now, 2).is_ok());
+        assert!(limiter.check(&rule, key(1), now, 2).is_ok());
+    }
+
+    #[test]
+    fn ipv6_callers_are_grouped_by_network() {
+        assert_eq!(
+            Caller::ip(ip("2001:db8:1:2:3:4:5:6")),
+            Caller::ip(ip("2001:db8:1:2:ffff::1"))
+        );
+        assert_ne!(
+            Caller::ip(ip("2001:db8:1:2::1")),
+            Caller::ip(ip("2001:db8:1:3::1"))
+        );
+        assert_eq!(
+            Caller::ip(ip("::ffff:10.0.0.1")),
+            Caller::Ip(ip("10.0.0.1"))
+        );
+        assert_eq!(Caller::ip(ip("10.0.0.1")), Caller::Ip(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_peer() {
+        let trusted = [ip("10.0.0.1")];
+        assert_eq!(
+            client_ip(ip("1.1.1.1"), &["2.2.2.2"], &trusted),
+            ip("1.1.1.1")
+        );
+    }
+
+    #[test]
+    fn trusted_hops_are_skipped() {
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &["3.3.3.3, 2.2.2.2", "10.0.0.2"], &trusted),
+            ip("2.2.2.2")
+        );
+    }
+
+    #[test]
+    fn walk_stops_at_the_first_untrusted_hop() {
+        let trusted = [ip("10.0.0.1")];
+        // A client may prepend any addresses, only the hop added by the proxy is used
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &["10.0.0.1, 6.6.6.6, 2.2.2.2"], &trusted),
+            ip("2.2.2.2")
+        );
+    }
+
+    #[test]
+    fn malformed_hop_stops_the_walk() {
+        let trusted = [ip("10.0.0.1")];
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &["2.2.2.2, unknown"], &trusted),
+            ip("10.0.0.1")
+        );
+        assert_eq!(client_ip(ip("10.0.0.1"), &[], &trusted), ip("10.0.0.1"));
+        // All hops are trusted, so the first one is the client
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &["10.0.0.1"], &trusted),
+            ip("10.0.0.1")
+        );
+    }
+}
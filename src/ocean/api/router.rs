@@ -1,13 +1,33 @@
 use crate::api;
+use crate::config;
 use crate::controller;
 use crate::db;
 use crate::json_rpc;
-use hyper::body;
-use hyper::body::Buf;
+use crate::metrics;
+use crate::model::session;
+use crate::model::user;
+use crate::model::user_group;
+use diesel::prelude::*;
+use hyper::body::HttpBody;
 use hyper::header;
 use hyper::{Body, Method, Request, Response, StatusCode};
 use log::{error, info};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const REQUEST_ID_LENGTH: usize = 16;
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+enum BodyError {
+    TooLarge,
+    Timeout,
+    Hyper(hyper::Error),
+}
 
 lazy_static! {
     static ref METHODS: HashMap<String, Rh> = {
@@ -42,6 +62,15 @@ lazy_static! {
             "user.changePassword".to_string(),
             Rh(controller::user::change_password),
         );
+        m.insert("user.logout".to_string(), Rh(controller::user::logout));
+        m.insert(
+            "user.logoutAll".to_string(),
+            Rh(controller::user::logout_all),
+        );
+        m.insert(
+            "user.getSessions".to_string(),
+            Rh(controller::user::get_sessions),
+        );
         m.insert(
             "comment.create".to_string(),
             Rh(controller::comment::create),
@@ -58,6 +87,14 @@ lazy_static! {
             "comment.delete".to_string(),
             Rh(controller::comment::delete),
         );
+        m.insert(
+            "permission.getAll".to_string(),
+            Rh(controller::permission::get_all),
+        );
+        m.insert(
+            "permission.update".to_string(),
+            Rh(controller::permission::update),
+        );
         m.insert(
             "search.getById".to_string(),
             Rh(controller::search::get_by_id),
@@ -80,7 +117,62 @@ lazy_static! {
 
 struct Rh(controller::RequestHandler);
 
+// Data taken from HTTP headers and shared by all calls of a request
+#[derive(Clone)]
+struct RequestContext {
+    request_id: String,
+    session_token: Option<String>,
+    user_agent: Option<String>,
+    client_ip: Option<IpAddr>,
+    locale: api::Locale,
+}
+
+// Collected while a call is handled for its log entry
+#[derive(Default)]
+struct CallStats {
+    user_id: Option<i32>,
+    // API code, JSON-RPC responses carry standard codes for some of them
+    error_code: Option<api::error::ErrorCode>,
+    handler_time: Option<Duration>,
+    // Read when the call is logged, a timed out handler may still be running
+    db_time: Option<db::DbTime>,
+}
+
+// A panic while handling a request is answered with an internal error instead of
+// dropping the connection
 pub async fn route(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+    match tokio::spawn(route_request(req)).await {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Request panic: {}", e);
+            metrics::observe_request_error(api::error::INTERNAL_SERVER_ERROR);
+            let resp = error_response(
+                serde_json::Value::Null,
+                api::error::INTERNAL_SERVER_ERROR,
+                None,
+                &api::Locale::default(),
+            );
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(serde_json::to_string(&resp).unwrap()))
+                .unwrap())
+        }
+    }
+}
+
+async fn route_request(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+    if req.method() == Method::GET {
+        match req.uri().path() {
+            "/health" => return Ok(api::health::health()),
+            "/ready" => return Ok(api::health::ready().await),
+            _ => {}
+        }
+    }
+
+    if req.method() == Method::OPTIONS && req.uri().path() == "/api" {
+        return Ok(api::cors::preflight(&req));
+    }
+
     if req.method() != Method::POST || req.uri().path() != "/api" {
         info!(
             "Bad request: method: {}, URL: {}",
@@ -93,72 +185,584 @@ pub async fn route(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
             .unwrap());
     }
 
-    let whole_body = body::aggregate(req).await?;
-    let bytes = whole_body.bytes();
-    let raw_req = String::from_utf8(bytes.to_vec()).unwrap();
+    let context = RequestContext {
+        request_id: request_id(&req),
+        session_token: req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|v| v.to_string()),
+        user_agent: req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string()),
+        client_ip: client_ip(&req),
+        locale: api::Locale::from_accept_language(
+            req.headers()
+                .get(header::ACCEPT_LANGUAGE)
+                .and_then(|v| v.to_str().ok()),
+        ),
+    };
+
+    let request_id = context.request_id.clone();
+    let request_headers = req.headers().clone();
 
-    info!("Request: {}", raw_req);
+    let bytes = match read_body(req).await {
+        Ok(b) => b,
+        Err(BodyError::Hyper(e)) => return Err(e),
+        Err(e) => {
+            let (status, text) = match e {
+                BodyError::TooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "Payload too large"),
+                _ => (StatusCode::REQUEST_TIMEOUT, "Request timeout"),
+            };
+            info!("Request {}: {}", request_id, text.to_lowercase());
 
-    let json_rpc_req = serde_json::from_slice::<json_rpc::Request>(bytes);
+            let mut response = Response::builder()
+                .status(status)
+                .header(header::CONNECTION, "close")
+                .body(Body::from(text))
+                .unwrap();
+            set_headers(&request_id, &request_headers, &mut response);
+            return Ok(response);
+        }
+    };
 
-    let json_rpc_resp = if let Ok(r) = json_rpc_req {
-        exec(r)
-    } else {
-        let mut resp = json_rpc::Response::default();
-        resp.error = Some(json_rpc::Error::from_api_error(&api::Error::new(
-            api::error::PARSE_ERROR,
-            Some(json_rpc_req.err().unwrap().to_string()),
-        )));
-        resp
+    // Invalid UTF-8 is reported by the parser as well
+    let json_rpc_resp = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Array(batch)) => handle_batch(batch, context).await,
+        Ok(v) => handle(v, context)
+            .await
+            .map(|r| serde_json::to_value(&r).unwrap()),
+        Err(e) => {
+            info!("Request: parse error, {} bytes", bytes.len());
+            metrics::observe_request_error(api::error::PARSE_ERROR);
+            Some(
+                serde_json::to_value(error_response(
+                    serde_json::Value::Null,
+                    api::error::PARSE_ERROR,
+                    Some(e.to_string()),
+                    &context.locale,
+                ))
+                .unwrap(),
+            )
+        }
     };
 
-    let raw_resp = serde_json::to_string(&json_rpc_resp).unwrap();
-    info!("Response: {}", raw_resp);
+    let mut response = match json_rpc_resp {
+        Some(r) => Response::new(Body::from(serde_json::to_string(&r).unwrap())),
+        None => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap(),
+    };
 
-    let mut response = Response::new(Body::from(raw_resp));
-    response.headers_mut().insert(
-        "Access-Control-Allow-Origin",
-        header::HeaderValue::from_static("*"),
-    );
+    set_headers(&request_id, &request_headers, &mut response);
 
     Ok(response)
 }
 
-fn exec(req: json_rpc::Request) -> json_rpc::Response {
-    let mut resp = json_rpc::Response::default();
+// Headers of every response to an API request, including rejected ones
+fn set_headers(
+    request_id: &str,
+    request_headers: &header::HeaderMap,
+    response: &mut Response<Body>,
+) {
+    if let Ok(v) = header::HeaderValue::from_str(request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, v);
+    }
+
+    api::cors::set_origin(request_headers, response);
+}
+
+// Responses of a batch are returned in order of its requests, without notifications
+async fn handle_batch(
+    batch: Vec<serde_json::Value>,
+    context: RequestContext,
+) -> Option<serde_json::Value> {
+    let max_batch_size = config::get().server.max_batch_size;
 
-    if let Some(id) = req.id {
-        resp.id = id;
+    if batch.is_empty() || batch.len() > max_batch_size {
+        let data = format!("batch size must be from 1 to {}", max_batch_size);
+        metrics::observe_request_error(api::error::INVALID_REQUEST);
+        let resp = error_response(
+            serde_json::Value::Null,
+            api::error::INVALID_REQUEST,
+            Some(data),
+            &context.locale,
+        );
+        return Some(serde_json::to_value(&resp).unwrap());
     }
 
-    let method = req.method;
-    resp.method = method.clone();
+    let mut responses = Vec::new();
 
-    match METHODS.get(&method) {
-        Some(func) => {
-            let db = db::Db::new();
-            let data = controller::RequestData::new(db, req.params);
-            let result = func.0(data);
+    if config::get().server.parallel_batch {
+        let handles = batch
+            .into_iter()
+            .map(|v| tokio::spawn(handle(v, context.clone())))
+            .collect::<Vec<_>>();
 
-            match result {
-                Ok(r) => resp.result = r,
+        for h in handles {
+            match h.await {
+                Ok(r) => responses.extend(r),
                 Err(e) => {
-                    let api_err = e.downcast_ref::<api::error::Error>();
-                    if let Some(i) = api_err {
-                        resp.error = Some(json_rpc::Error::from_api_error(i));
-                    } else {
-                        error!("{}", e);
-                        let server_err =
-                            api::error::Error::new(api::error::INTERNAL_SERVER_ERROR, None);
-                        resp.error = Some(json_rpc::Error::from_api_error(&server_err));
-                    }
+                    error!("Batch request error: {}", e);
+                    metrics::observe_request_error(api::error::INTERNAL_SERVER_ERROR);
+                    responses.push(error_response(
+                        serde_json::Value::Null,
+                        api::error::INTERNAL_SERVER_ERROR,
+                        None,
+                        &context.locale,
+                    ));
                 }
-            };
+            }
         }
+    } else {
+        for v in batch {
+            responses.extend(handle(v, context.clone()).await);
+        }
+    }
+
+    if responses.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_value(&responses).unwrap())
+    }
+}
+
+// Notifications are executed but never answered
+async fn handle(value: serde_json::Value, context: RequestContext) -> Option<json_rpc::Response> {
+    let method = value
+        .get("method")
+        .and_then(|m| m.as_str())
+        .unwrap_or_default()
+        .to_string();
+    api::logging::request(&context.request_id, &method, &value);
+
+    let req = match serde_json::from_value::<json_rpc::Request>(value) {
+        Ok(r) if r.is_valid() => r,
+        Ok(_) => {
+            metrics::observe_request_error(api::error::INVALID_REQUEST);
+            return Some(error_response(
+                serde_json::Value::Null,
+                api::error::INVALID_REQUEST,
+                None,
+                &context.locale,
+            ));
+        }
+        Err(e) => {
+            metrics::observe_request_error(api::error::INVALID_REQUEST);
+            return Some(error_response(
+                serde_json::Value::Null,
+                api::error::INVALID_REQUEST,
+                Some(e.to_string()),
+                &context.locale,
+            ));
+        }
+    };
+
+    let context = RequestContext {
+        locale: context.locale.prefer(req.lang.as_deref()),
+        ..context
+    };
+
+    let request_id = context.request_id.clone();
+    let notification = req.is_notification();
+    let start = Instant::now();
+    let (resp, stats) = exec(req, context).await;
+
+    let latency = start.elapsed();
+
+    api::logging::call(&api::logging::Call {
+        request_id: &request_id,
+        method: &method,
+        user_id: stats.user_id,
+        error_code: stats.error_code,
+        handler_time: stats.handler_time,
+        db_time: stats.db_time.as_ref().map(db::DbTime::get),
+        latency,
+    });
+
+    let method_label = if METHODS.contains_key(&method) {
+        &method
+    } else {
+        metrics::UNKNOWN_METHOD
+    };
+    metrics::observe_call(method_label, stats.error_code, latency);
+
+    if notification {
+        None
+    } else {
+        api::logging::response(&request_id, &method, stats.user_id, &resp);
+        Some(resp)
+    }
+}
+
+// Handlers do blocking database calls, so they are run on the blocking thread pool.
+// A handler which exceeds its timeout is not cancelled, only its response is dropped.
+async fn exec(req: json_rpc::Request, context: RequestContext) -> (json_rpc::Response, CallStats) {
+    let id = req.id.unwrap_or(serde_json::Value::Null);
+    let method = req.method;
+
+    let handler = match METHODS.get(&method) {
+        Some(func) => func.0,
         None => {
-            let server_err = api::error::Error::new(api::error::METHOD_NOT_FOUND, Some(method));
-            resp.error = Some(json_rpc::Error::from_api_error(&server_err));
+            let err = api::Error::new(api::error::METHOD_NOT_FOUND, Some(method.into()));
+            return failed(id, err, CallStats::default(), &context.locale);
         }
+    };
+
+    // Anonymous calls are limited by IP before any database work,
+    // calls with a session are limited after authentication
+    if context.session_token.is_none() {
+        if let Err(e) = check_ip_rate(&method, &context) {
+            return failed(id, e.into(), CallStats::default(), &context.locale);
+        }
+    }
+
+    let timeout = method_timeout(&method);
+    let params = req.params;
+    let locale = context.locale.clone();
+    // Shared to keep the user id of a call which has timed out
+    let stats = Arc::new(Mutex::new(CallStats::default()));
+    let task = {
+        let method = method.clone();
+        let stats = stats.clone();
+        tokio::task::spawn_blocking(move || {
+            let start = Instant::now();
+            let result = call(handler, &method, params, context, &stats);
+            stats.lock().unwrap().handler_time = Some(start.elapsed());
+            api_result(result)
+        })
+    };
+
+    let result = match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(r)) => r,
+        Ok(Err(e)) => {
+            error!("Request handler error: {}", e);
+            Err(api::Error::new(api::error::INTERNAL_SERVER_ERROR, None))
+        }
+        Err(_) => {
+            error!("Request timeout: {}", method);
+            Err(api::Error::new(api::error::TIMEOUT, Some(method.into())))
+        }
+    };
+
+    let stats = std::mem::take(&mut *stats.lock().unwrap());
+    match result {
+        Ok(r) => (json_rpc::Response::result(id, r), stats),
+        Err(e) => failed(id, e, stats, &locale),
+    }
+}
+
+fn failed(
+    id: serde_json::Value,
+    err: api::Error,
+    stats: CallStats,
+    locale: &api::Locale,
+) -> (json_rpc::Response, CallStats) {
+    let stats = CallStats {
+        error_code: Some(err.code()),
+        ..stats
+    };
+    let resp = json_rpc::Response::error(id, json_rpc::Error::from_api_error(&err, locale));
+    (resp, stats)
+}
+
+// Body is limited by server.max_body_size bytes and server.read_timeout seconds
+async fn read_body(req: Request<Body>) -> Result<Vec<u8>, BodyError> {
+    let config = config::get();
+    let max_size = config.server.max_body_size;
+
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if content_length.is_some_and(|l| l > max_size) {
+        return Err(BodyError::TooLarge);
+    }
+
+    let mut body = req.into_body();
+    let read = async move {
+        let mut bytes = Vec::with_capacity(content_length.unwrap_or_default());
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(BodyError::Hyper)?;
+            if bytes.len() + chunk.len() > max_size {
+                return Err(BodyError::TooLarge);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes)
+    };
+
+    let timeout = Duration::from_secs(config.server.read_timeout);
+    tokio::time::timeout(timeout, read)
+        .await
+        .unwrap_or(Err(BodyError::Timeout))
+}
+
+fn client_ip(req: &Request<Body>) -> Option<IpAddr> {
+    let peer = req.extensions().get::<SocketAddr>()?.ip();
+    let forwarded = req
+        .headers()
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect::<Vec<_>>();
+
+    Some(api::rate_limit::client_ip(
+        peer,
+        &forwarded,
+        &config::get().rate_limit.trusted_proxies,
+    ))
+}
+
+// Request id given by a proxy is kept to correlate its logs with ours
+fn request_id(req: &Request<Body>) -> String {
+    let given = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 64);
+
+    match given {
+        Some(v) => v.to_string(),
+        None => rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(REQUEST_ID_LENGTH)
+            .collect(),
+    }
+}
+
+fn method_timeout(method: &str) -> Duration {
+    let config = config::get();
+    let server = &config.server;
+    let secs = server
+        .method_timeouts
+        .get(method)
+        .copied()
+        .unwrap_or(server.request_timeout);
+    Duration::from_secs(secs)
+}
+
+// Errors other than API ones are logged and reported as internal
+fn api_result(result: controller::RequestResult) -> Result<Option<serde_json::Value>, api::Error> {
+    result.map_err(|e| {
+        api::error::find_error(e.as_ref()).unwrap_or_else(|| {
+            error!("{}", e);
+            api::Error::new(api::error::INTERNAL_SERVER_ERROR, None)
+        })
+    })
+}
+
+fn call(
+    handler: controller::RequestHandler,
+    method: &str,
+    params: Option<serde_json::Value>,
+    context: RequestContext,
+    stats: &Mutex<CallStats>,
+) -> controller::RequestResult {
+    let db = db::Db::new().map_err(|e| {
+        error!("Database pool error: {}", e);
+        api::make_error(api::error::SERVICE_UNAVAILABLE)
+    })?;
+    stats.lock().unwrap().db_time = Some(db.time());
+
+    let has_token = context.session_token.is_some();
+    let (session, user) = authenticate(&db, context.session_token.clone())?.unzip();
+    stats.lock().unwrap().user_id = user.as_ref().map(|u| u.id);
+
+    match &user {
+        Some(u) => api::rate_limit::check(method, api::rate_limit::Caller::User(u.id))?,
+        // A session which has failed authentication is limited as anonymous
+        None if has_token => check_ip_rate(method, &context)?,
+        None => {}
+    }
+
+    let group = find_group(&db, &user)?;
+    authorize(&db, &user, &group, method)?;
+
+    let client = controller::Client {
+        session,
+        user,
+        group,
+        user_agent: context.user_agent,
+        locale: context.locale,
+    };
+
+    handler(controller::RequestData::new(db, params, client))
+}
+
+fn check_ip_rate(method: &str, context: &RequestContext) -> Result<(), api::Failure> {
+    match context.client_ip {
+        Some(ip) => api::rate_limit::check(method, api::rate_limit::Caller::ip(ip)),
+        None => Ok(()),
+    }
+}
+
+fn error_response(
+    id: serde_json::Value,
+    code: api::error::ErrorCode,
+    data: Option<String>,
+    locale: &api::Locale,
+) -> json_rpc::Response {
+    let err = api::Error::new(code, data.map(serde_json::Value::from));
+    json_rpc::Response::error(id, json_rpc::Error::from_api_error(&err, locale))
+}
+
+fn authenticate(
+    db: &db::Db,
+    session_token: Option<String>,
+) -> Result<Option<(session::Session, user::User)>, Box<dyn std::error::Error>> {
+    use crate::model::schema::users::dsl::*;
+
+    let session_token = match session_token {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+
+    // Unknown or expired sessions are served as guests, so public methods
+    // like user.auth work and others fail with NOT_AUTHORIZED
+    let session = match api::session::find(&db.conn, &session_token)? {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+
+    let user = users
+        .filter(id.eq(session.user_id))
+        .first::<user::User>(&db.conn)
+        .optional()?;
+
+    Ok(user.map(|u| (session, u)))
+}
+
+fn find_group(
+    db: &db::Db,
+    user: &Option<user::User>,
+) -> Result<user_group::UserGroup, Box<dyn std::error::Error>> {
+    use crate::model::schema::user_groups::dsl::*;
+
+    let query = user_groups.into_boxed();
+
+    let query = match user {
+        Some(u) => query.filter(id.eq(u.group_id)),
+        None => query.filter(code.eq(user_group::GUEST)),
+    };
+
+    Ok(query.first::<user_group::UserGroup>(&db.conn)?)
+}
+
+fn authorize(
+    db: &db::Db,
+    user: &Option<user::User>,
+    group: &user_group::UserGroup,
+    method: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if controller::permission::is_permitted(&db.conn, group.id, method)? {
+        return Ok(());
+    }
+
+    let failure = if user.is_some() {
+        api::Failure::PermissionDenied(Some(method.to_string()))
+    } else {
+        api::Failure::Unauthenticated
+    };
+
+    Err(failure.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> RequestContext {
+        RequestContext {
+            request_id: "test".to_string(),
+            session_token: None,
+            user_agent: None,
+            client_ip: None,
+            locale: api::Locale::default(),
+        }
+    }
+
+    // Unknown methods are answered before any database work
+    async fn call(value: serde_json::Value) -> Option<serde_json::Value> {
+        handle(value, context())
+            .await
+            .map(|r| serde_json::to_value(&r).unwrap())
+    }
+
+    #[tokio::test]
+    async fn ids_are_echoed() {
+        for id in &[json!(1), json!("a"), json!(null)] {
+            let resp = call(json!({ "jsonrpc": "2.0", "method": "x.y", "id": id }))
+                .await
+                .unwrap();
+            assert_eq!(resp["id"], *id);
+            assert_eq!(resp["jsonrpc"], "2.0");
+            assert_eq!(resp["error"]["code"], json_rpc::error::METHOD_NOT_FOUND);
+            assert_eq!(resp["error"]["data"]["code"], api::error::METHOD_NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn notifications_are_not_answered() {
+        assert!(call(json!({ "jsonrpc": "2.0", "method": "x.y" }))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn invalid_ids_are_rejected() {
+        for id in &[json!({}), json!([1])] {
+            let resp = call(json!({ "jsonrpc": "2.0", "method": "x.y", "id": id }))
+                .await
+                .unwrap();
+            assert_eq!(resp["id"], serde_json::Value::Null);
+            assert_eq!(resp["error"]["code"], json_rpc::error::INVALID_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn request_without_method_is_invalid() {
+        let resp = call(json!({ "jsonrpc": "2.0", "id": 1 })).await.unwrap();
+        assert_eq!(resp["error"]["code"], json_rpc::error::INVALID_REQUEST);
+        assert_eq!(resp["error"]["data"]["code"], api::error::INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn batch_of_notifications_has_no_body() {
+        let batch = vec![
+            json!({ "jsonrpc": "2.0", "method": "x.y" }),
+            json!({ "jsonrpc": "2.0", "method": "x.z" }),
+        ];
+        assert!(handle_batch(batch, context()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn batch_answers_requests_in_order() {
+        let batch = vec![
+            json!({ "jsonrpc": "2.0", "method": "x.y", "id": 2 }),
+            json!({ "jsonrpc": "2.0", "method": "x.y" }),
+            json!({ "jsonrpc": "2.0", "method": "x.y", "id": 1 }),
+        ];
+        let resp = handle_batch(batch, context()).await.unwrap();
+        let ids = resp
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["id"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![json!(2), json!(1)]);
+    }
+
+    #[tokio::test]
+    async fn empty_batch_is_invalid() {
+        let resp = handle_batch(Vec::new(), context()).await.unwrap();
+        assert_eq!(resp["error"]["code"], json_rpc::error::INVALID_REQUEST);
+        assert_eq!(resp["id"], serde_json::Value::Null);
     }
-    resp
 }
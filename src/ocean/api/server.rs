@@ -1,7 +1,17 @@
+use super::connection::{Busy, Connection};
 use super::router;
 use crate::config;
+use crate::metrics;
 use hyper::service::{make_service_fn, service_fn};
-use log::{error, info};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use log::{error, info, warn};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::stream::StreamExt;
+use tokio::sync::Semaphore;
 
 #[derive(Default)]
 pub struct Server;
@@ -11,14 +21,72 @@ impl Server {
         Server
     }
 
-    pub async fn listen(&self) {
-        let port = config::CONFIG.server.port;
-        let addr = ([0, 0, 0, 0], port).into();
+    // Stops accepting connections when shutdown is resolved and returns after
+    // responses to in-flight requests are sent
+    pub async fn listen(&self, shutdown: impl Future<Output = ()>) {
+        let config = config::get();
+        let port = config.server.port;
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
 
-        let service =
-            make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(router::route)) });
+        let mut listener = match TcpListener::bind(addr).await {
+            Ok(l) => l,
+            Err(e) => {
+                error!("API server error: {}", e);
+                return;
+            }
+        };
 
-        let server = hyper::Server::bind(&addr).serve(service);
+        // Connections over the limit are closed right after accept
+        let permits = Arc::new(Semaphore::new(config.server.max_connections));
+        let incoming = listener.incoming().filter_map(|stream| {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("Connection accept error: {}", e);
+                    return None;
+                }
+            };
+
+            let remote_addr = stream.peer_addr().ok()?;
+            let permit = match permits.clone().try_acquire_owned() {
+                Ok(p) => p,
+                Err(_) => {
+                    warn!(
+                        "Connection from {} is rejected: too many connections",
+                        remote_addr
+                    );
+                    return None;
+                }
+            };
+
+            let read_timeout = Duration::from_secs(config::get().server.read_timeout);
+            Some(Ok::<_, std::io::Error>(Connection::new(
+                stream,
+                remote_addr,
+                read_timeout,
+                permit,
+            )))
+        });
+
+        let service = make_service_fn(|conn: &Connection| {
+            let state = conn.state();
+            let remote_addr = conn.remote_addr();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |mut req| {
+                    let busy = Busy::new(state.clone());
+                    req.extensions_mut().insert(remote_addr);
+                    async move {
+                        let resp = router::route(req).await;
+                        drop(busy);
+                        resp
+                    }
+                }))
+            }
+        });
+
+        let server = hyper::Server::builder(hyper::server::accept::from_stream(incoming))
+            .serve(service)
+            .with_graceful_shutdown(shutdown);
 
         info!("API server listen on port {}", port);
 
@@ -26,4 +94,56 @@ impl Server {
             error!("API server error: {}", e);
         }
     }
+
+    // Serves GET /metrics on metrics.address and metrics.port if enabled
+    pub async fn listen_metrics(&self, shutdown: impl Future<Output = ()>) {
+        let config = config::get();
+        if !config.metrics.enabled {
+            return;
+        }
+
+        let addr = SocketAddr::from((config.metrics.address, config.metrics.port));
+        let service = make_service_fn(|_| async {
+            Ok::<_, hyper::Error>(service_fn(|req| async {
+                Ok::<_, hyper::Error>(metrics_response(req).await)
+            }))
+        });
+
+        let server = match hyper::Server::try_bind(&addr) {
+            Ok(b) => b.serve(service).with_graceful_shutdown(shutdown),
+            Err(e) => {
+                error!("Metrics server error: {}", e);
+                return;
+            }
+        };
+
+        info!("Metrics server listen on {}", addr);
+
+        if let Err(e) = server.await {
+            error!("Metrics server error: {}", e);
+        }
+    }
+}
+
+async fn metrics_response(req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    match tokio::task::spawn_blocking(metrics::gather).await {
+        Ok(body) => Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(e) => {
+            error!("Metrics error: {}", e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap()
+        }
+    }
 }
@@ -0,0 +1,83 @@
+use crate::config;
+use crate::db;
+use crate::model::session;
+use chrono::prelude::*;
+use diesel::prelude::*;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+const TOKEN_LENGTH: usize = 64;
+
+// Only digests of tokens are stored, so sessions can't be used by those
+// who read the database. Tokens are random, a slow hash isn't needed.
+fn digest(session_token: &str) -> String {
+    Sha256::digest(session_token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Returns the token itself, it is given to the client only once
+pub fn create(
+    conn: &db::TimedConnection,
+    session_user_id: i32,
+    session_user_agent: Option<String>,
+) -> QueryResult<String> {
+    use crate::model::schema::sessions;
+    use crate::model::schema::sessions::dsl::*;
+
+    let now = Utc::now().naive_utc();
+
+    diesel::delete(sessions.filter(sessions::user_id.eq(session_user_id).and(expire_ts.le(now))))
+        .execute(conn)?;
+
+    let session_token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .collect();
+
+    let new_session = session::NewSession {
+        user_id: session_user_id,
+        token_hash: digest(&session_token),
+        user_agent: session_user_agent,
+        expire_ts: now + chrono::Duration::seconds(config::get().server.session_lifetime),
+    };
+
+    diesel::insert_into(sessions)
+        .values(&new_session)
+        .execute(conn)?;
+
+    Ok(session_token)
+}
+
+// Returns a session only until it expires
+pub fn find(
+    conn: &db::TimedConnection,
+    session_token: &str,
+) -> QueryResult<Option<session::Session>> {
+    use crate::model::schema::sessions::dsl::*;
+
+    sessions
+        .filter(
+            token_hash
+                .eq(digest(session_token))
+                .and(expire_ts.gt(Utc::now().naive_utc())),
+        )
+        .first::<session::Session>(conn)
+        .optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_is_hex_sha256() {
+        assert_eq!(
+            digest("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(digest("abc"), digest("abd"));
+    }
+}
@@ -1,24 +1,158 @@
 use crate::api::server;
 use crate::config;
-use crate::telegram_bot::TelegramBot;
+use crate::logger;
+use crate::telegram_bot::{self, TelegramBot};
+use log::{error, info, warn};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::oneshot;
 
 pub struct App {
-    _telegram_bot: Option<TelegramBot>,
+    // Stopping of the bot waits for its requests, so it is done in blocking tasks
+    telegram_bot: Arc<Mutex<Option<TelegramBot>>>,
 }
 
 impl App {
     pub fn new() -> Self {
-        let bot = if config::CONFIG.telegram_bot.enabled {
-            Some(TelegramBot::new())
-        } else {
-            None
-        };
-
-        App { _telegram_bot: bot }
+        App {
+            telegram_bot: Arc::new(Mutex::new(start_bot())),
+        }
     }
 
     pub async fn start(&self) {
+        let (stop, stopped) = oneshot::channel::<()>();
+        let (stop_metrics, metrics_stopped) = oneshot::channel::<()>();
+        tokio::spawn(async {
+            let server = server::Server::new();
+            server
+                .listen_metrics(async {
+                    metrics_stopped.await.ok();
+                })
+                .await;
+        });
+
         let server = server::Server::new();
-        server.listen().await;
+        let listen = server.listen(async {
+            stopped.await.ok();
+        });
+        tokio::pin!(listen);
+
+        tokio::select! {
+            _ = &mut listen => {}
+            _ = self.watch_signals() => {
+                let timeout = Duration::from_secs(config::get().server.shutdown_timeout);
+                let deadline = Instant::now() + timeout;
+
+                info!("Shutting down, waiting for in-flight requests");
+                stop.send(()).ok();
+                stop_metrics.send(()).ok();
+
+                if tokio::time::timeout(timeout, listen).await.is_err() {
+                    warn!("In-flight requests are not finished in {} seconds", timeout.as_secs());
+                }
+
+                self.shutdown(deadline).await;
+            }
+        }
+    }
+
+    // Config is reloaded on SIGHUP, returns on SIGTERM or SIGINT
+    async fn watch_signals(&self) {
+        let (mut hangup, mut terminate, mut interrupt) = match listen_signals() {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Signal handler error: {}", e);
+                return std::future::pending().await;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => self.reload(),
+                _ = terminate.recv() => {
+                    info!("SIGTERM received");
+                    return;
+                }
+                _ = interrupt.recv() => {
+                    info!("SIGINT received");
+                    return;
+                }
+            }
+        }
+    }
+
+    // Waits until the deadline for the bot loop and sending of messages
+    async fn shutdown(&self, deadline: Instant) {
+        let bot = self.telegram_bot.clone();
+        let stop = tokio::task::spawn_blocking(move || {
+            // The bot finishes the current batch of updates
+            *bot.lock().unwrap() = None;
+            telegram_bot::flush(deadline.saturating_duration_since(Instant::now()))
+        });
+
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match tokio::time::timeout(timeout, stop).await {
+            Ok(Ok(true)) => {}
+            Ok(Ok(false)) => warn!("Pending Telegram messages are not sent"),
+            Ok(Err(e)) => error!("Telegram bot stop error: {}", e),
+            Err(_) => warn!(
+                "Telegram bot is not stopped in {} seconds",
+                timeout.as_secs()
+            ),
+        }
+
+        info!("Ocean stopped");
+    }
+
+    fn reload(&self) {
+        let old_config = config::get();
+
+        let changes = match config::reload() {
+            Ok(c) => c,
+            Err(errors) => {
+                for e in errors {
+                    error!("Config error: {}", e);
+                }
+                error!("Config is not reloaded");
+                return;
+            }
+        };
+
+        if changes.is_empty() {
+            info!("Config reloaded without changes");
+            return;
+        }
+
+        info!("Config reloaded: {}", changes.join(", "));
+
+        let new_config = config::get();
+        logger::configure(&new_config.log);
+
+        if old_config.telegram_bot != new_config.telegram_bot {
+            let bot = self.telegram_bot.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut bot = bot.lock().unwrap();
+                // The old loop is stopped before the new one is started
+                *bot = None;
+                *bot = start_bot();
+            });
+        }
+    }
+}
+
+fn listen_signals() -> std::io::Result<(Signal, Signal, Signal)> {
+    Ok((
+        signal(SignalKind::hangup())?,
+        signal(SignalKind::terminate())?,
+        signal(SignalKind::interrupt())?,
+    ))
+}
+
+fn start_bot() -> Option<TelegramBot> {
+    if config::get().telegram_bot.enabled {
+        Some(TelegramBot::new())
+    } else {
+        None
     }
 }
@@ -1,32 +1,99 @@
-use serde_derive::Deserialize;
+use log::LevelFilter;
+use log::{error, warn};
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, RwLock};
+
+const ENV_PREFIX: &str = "OCEAN_";
+const SSL_MODES: &[&str] = &[
+    "disable",
+    "allow",
+    "prefer",
+    "require",
+    "verify-ca",
+    "verify-full",
+];
+// Values of these fields are hidden in the log of config changes
+const SECRET_FIELDS: &[&str] = &["postgres.password", "postgres.url", "telegram_bot.token"];
+// Fields without default values, so they are missing in serialized defaults
+const OPTIONAL_FIELDS: &[&str] = &["postgres.url", "postgres.ssl_mode"];
+// Changes of these sections take effect only after restart
+const RESTART_SECTIONS: &[&str] = &[
+    "server.port",
+    "server.max_connections",
+    "postgres.",
+    "metrics.",
+];
 
 lazy_static! {
-    pub static ref CONFIG: Config = Config::new();
+    static ref CONFIG: RwLock<Arc<Config>> = RwLock::new(Arc::new(Config::new()));
+}
+
+// Returns the current config. Keep the result only for the time of a single
+// operation to see changes made by reload.
+pub fn get() -> Arc<Config> {
+    CONFIG.read().unwrap().clone()
+}
+
+// Loads config again and returns descriptions of changed fields
+pub fn reload() -> Result<Vec<String>, Vec<String>> {
+    let new_config = Config::load()?;
+    let changes = diff(&get(), &new_config);
+    *CONFIG.write().unwrap() = Arc::new(new_config);
+    Ok(changes)
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     pub server: Server,
     pub postgres: Postgres,
     pub telegram_bot: TelegramBot,
+    pub log: Log,
+    pub cors: Cors,
+    pub rate_limit: RateLimit,
+    pub metrics: Metrics,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Server {
     pub port: u16,
+    pub session_lifetime: i64,
+    pub max_batch_size: usize,
+    pub parallel_batch: bool,
+    pub request_timeout: u64,
+    pub method_timeouts: HashMap<String, u64>,
+    pub shutdown_timeout: u64,
+    // Bytes
+    pub max_body_size: usize,
+    // Seconds for a client to send request headers and then the body
+    pub read_timeout: u64,
+    pub max_connections: usize,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct Postgres {
     pub host: String,
     pub port: u16,
     pub username: String,
     pub password: String,
     pub database: String,
+    pub url: Option<String>,
+    pub ssl_mode: Option<String>,
+    pub pool_size: u32,
+    pub connection_timeout: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
 pub struct TelegramBot {
     pub interval: i64,
     pub token: String,
@@ -34,22 +101,651 @@ pub struct TelegramBot {
     pub enabled: bool,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Log {
+    pub level: String,
+    pub format: LogFormat,
+    // Logging of API calls unless overridden for a method
+    pub requests: LogMode,
+    pub methods: HashMap<String, LogMode>,
+    // Values of these fields are masked in logged requests and responses
+    pub sensitive_fields: Vec<String>,
+    pub max_body_length: usize,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    // One JSON object per line
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogMode {
+    Off,
+    // Method, id and error code only
+    Summary,
+    Full,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Cors {
+    // "*" allows any origin
+    pub allow_origins: Vec<String>,
+    pub allow_methods: Vec<String>,
+    pub allow_headers: Vec<String>,
+    pub allow_credentials: bool,
+    // Seconds for browsers to cache preflight responses
+    pub max_age: u64,
+}
+
+// Prometheus metrics are served apart from the API, on a private address
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Metrics {
+    pub enabled: bool,
+    pub address: IpAddr,
+    pub port: u16,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RateLimit {
+    // Addresses of proxies whose X-Forwarded-For header is trusted
+    pub trusted_proxies: Vec<IpAddr>,
+    // Methods without a rule are not limited
+    pub methods: HashMap<String, Bucket>,
+}
+
+// Token bucket: up to `burst` calls at once, refilled by `per_minute` calls
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Bucket {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            port: 21000,
+            // 30 days in seconds
+            session_lifetime: 30 * 24 * 60 * 60,
+            max_batch_size: 50,
+            parallel_batch: false,
+            request_timeout: 30,
+            method_timeouts: HashMap::new(),
+            shutdown_timeout: 30,
+            max_body_size: 1024 * 1024,
+            read_timeout: 30,
+            max_connections: 1024,
+        }
+    }
+}
+
+impl Default for Postgres {
+    fn default() -> Self {
+        Postgres {
+            host: "localhost".into(),
+            port: 5432,
+            username: "ocean".into(),
+            password: "".into(),
+            database: "ocean".into(),
+            url: None,
+            ssl_mode: None,
+            pool_size: 10,
+            connection_timeout: 5,
+        }
+    }
+}
+
+impl Default for TelegramBot {
+    fn default() -> Self {
+        TelegramBot {
+            interval: 600,
+            token: "".into(),
+            url: "https://api.telegram.org".into(),
+            enabled: false,
+        }
+    }
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Log {
+            level: "info".into(),
+            format: LogFormat::Text,
+            requests: LogMode::Full,
+            methods: HashMap::new(),
+            sensitive_fields: vec!["token".into(), "password".into(), "session".into()],
+            max_body_length: 4096,
+        }
+    }
+}
+
+impl Log {
+    pub fn level_filter(&self) -> LevelFilter {
+        self.level.parse().unwrap_or(LevelFilter::Info)
+    }
+
+    pub fn method_mode(&self, method: &str) -> LogMode {
+        self.methods.get(method).copied().unwrap_or(self.requests)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            enabled: true,
+            address: IpAddr::from([127, 0, 0, 1]),
+            port: 21001,
+        }
+    }
+}
+
+impl Cors {
+    pub fn any_origin(&self) -> bool {
+        self.allow_origins.iter().any(|o| o == "*")
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors {
+            allow_origins: vec!["*".into()],
+            allow_methods: vec!["POST".into()],
+            allow_headers: vec![
+                "Authorization".into(),
+                "Content-Type".into(),
+                "Accept-Language".into(),
+                "X-Request-Id".into(),
+            ],
+            allow_credentials: false,
+            max_age: 24 * 60 * 60,
+        }
+    }
+}
+
 impl Config {
+    // Exits the process on errors because nothing can work without valid config
     pub fn new() -> Self {
-        let mut config_path = dirs::config_dir().unwrap();
-        config_path.push("ocean/ocean.toml");
+        Self::load().unwrap_or_else(|errors| {
+            for e in errors {
+                error!("Config error: {}", e);
+            }
+            process::exit(1);
+        })
+    }
+
+    // Values are taken from defaults, then from config file and then from
+    // OCEAN_<SECTION>_<FIELD> environment variables. All found errors are returned at once.
+    pub fn load() -> Result<Self, Vec<String>> {
+        let mut errors = Vec::new();
+
+        let mut value = match read_file() {
+            Ok(v) => v,
+            Err(e) => return Err(vec![e]),
+        };
+
+        let defaults = toml::Value::try_from(Config::default()).unwrap();
+        apply_env(&mut value, &defaults, env::vars(), &mut errors);
+
+        let config = Config {
+            server: section(&value, "server", &mut errors),
+            postgres: section(&value, "postgres", &mut errors),
+            telegram_bot: section(&value, "telegram_bot", &mut errors),
+            log: section(&value, "log", &mut errors),
+            cors: section(&value, "cors", &mut errors),
+            rate_limit: section(&value, "rate_limit", &mut errors),
+            metrics: section(&value, "metrics", &mut errors),
+        };
+
+        config.validate(&mut errors);
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        let mut check = |valid: bool, field: &str, reason: &str| {
+            if !valid {
+                errors.push(format!("{}: {}", field, reason));
+            }
+        };
 
-        if !config_path.exists() {
-            panic!("config path not exists: {}", config_path.to_str().unwrap());
+        let server = &self.server;
+        check(server.port != 0, "server.port", "must not be 0");
+        check(
+            server.session_lifetime > 0,
+            "server.session_lifetime",
+            "must be positive",
+        );
+        check(
+            server.max_batch_size > 0,
+            "server.max_batch_size",
+            "must be positive",
+        );
+        check(
+            server.request_timeout > 0,
+            "server.request_timeout",
+            "must be positive",
+        );
+        check(
+            server.max_body_size > 0,
+            "server.max_body_size",
+            "must be positive",
+        );
+        check(
+            server.read_timeout > 0,
+            "server.read_timeout",
+            "must be positive",
+        );
+        check(
+            server.max_connections > 0,
+            "server.max_connections",
+            "must be positive",
+        );
+        for (method, timeout) in &server.method_timeouts {
+            check(
+                *timeout > 0,
+                &format!("server.method_timeouts.{}", method),
+                "must be positive",
+            );
         }
 
-        let config_text = fs::read_to_string(config_path).unwrap();
-        toml::from_str(&config_text).unwrap()
+        let postgres = &self.postgres;
+        match &postgres.url {
+            Some(u) => check(
+                url::Url::parse(u).is_ok(),
+                "postgres.url",
+                "must be a valid URL",
+            ),
+            None => {
+                check(!postgres.host.is_empty(), "postgres.host", "must be set");
+                check(postgres.port != 0, "postgres.port", "must not be 0");
+                check(
+                    !postgres.database.is_empty(),
+                    "postgres.database",
+                    "must be set",
+                );
+            }
+        }
+        if let Some(mode) = &postgres.ssl_mode {
+            check(
+                SSL_MODES.contains(&mode.as_str()),
+                "postgres.ssl_mode",
+                &format!("must be one of: {}", SSL_MODES.join(", ")),
+            );
+        }
+        check(
+            postgres.pool_size > 0,
+            "postgres.pool_size",
+            "must be positive",
+        );
+        check(
+            postgres.connection_timeout > 0,
+            "postgres.connection_timeout",
+            "must be positive",
+        );
+        // Otherwise exhausted pool is reported as TIMEOUT instead of SERVICE_UNAVAILABLE
+        let min_timeout = self
+            .server
+            .method_timeouts
+            .values()
+            .copied()
+            .fold(self.server.request_timeout, u64::min);
+        check(
+            postgres.connection_timeout < min_timeout,
+            "postgres.connection_timeout",
+            "must be less than request and method timeouts",
+        );
+
+        let telegram_bot = &self.telegram_bot;
+        check(
+            telegram_bot.interval > 0,
+            "telegram_bot.interval",
+            "must be positive",
+        );
+        if telegram_bot.enabled {
+            check(
+                !telegram_bot.token.is_empty(),
+                "telegram_bot.token",
+                "must be set when bot is enabled",
+            );
+            check(
+                !telegram_bot.url.is_empty(),
+                "telegram_bot.url",
+                "must be set when bot is enabled",
+            );
+        }
+
+        check(
+            self.log.level.parse::<LevelFilter>().is_ok(),
+            "log.level",
+            "must be one of: off, error, warn, info, debug, trace",
+        );
+        check(
+            self.log.max_body_length > 0,
+            "log.max_body_length",
+            "must be positive",
+        );
+        for origin in &self.cors.allow_origins {
+            check(
+                header_value_is_valid(origin),
+                "cors.allow_origins",
+                "must be valid header values",
+            );
+        }
+        check(
+            !(self.cors.any_origin() && self.cors.allow_credentials),
+            "cors.allow_credentials",
+            "can't be enabled when any origin is allowed",
+        );
+        for method in &self.cors.allow_methods {
+            check(
+                hyper::Method::from_bytes(method.as_bytes()).is_ok(),
+                "cors.allow_methods",
+                "must be valid HTTP methods",
+            );
+        }
+        for name in &self.cors.allow_headers {
+            check(
+                hyper::header::HeaderName::from_bytes(name.as_bytes()).is_ok(),
+                "cors.allow_headers",
+                "must be valid header names",
+            );
+        }
+
+        for (method, bucket) in &self.rate_limit.methods {
+            check(
+                bucket.burst > 0 && bucket.per_minute > 0,
+                &format!("rate_limit.methods.{}", method),
+                "burst and per_minute must be positive",
+            );
+        }
     }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self::new()
+fn header_value_is_valid(value: &str) -> bool {
+    hyper::header::HeaderValue::from_str(value).is_ok()
+}
+
+// Path is taken from `--config <path>` argument, otherwise the default
+// ~/.config/ocean/ocean.toml is used if it exists
+fn config_path() -> Result<Option<PathBuf>, String> {
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return match args.next() {
+                Some(p) => Ok(Some(PathBuf::from(p))),
+                None => Err("--config: path is missing".into()),
+            };
+        } else if let Some(p) = arg.strip_prefix("--config=") {
+            return Ok(Some(PathBuf::from(p)));
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_file() -> Result<toml::Value, String> {
+    let path = match config_path()? {
+        Some(p) => {
+            if !p.exists() {
+                return Err(format!("config path not exists: {}", p.display()));
+            }
+            p
+        }
+        None => match dirs::config_dir() {
+            Some(mut p) => {
+                p.push("ocean/ocean.toml");
+                if !p.exists() {
+                    return Ok(toml::Value::Table(toml::value::Table::new()));
+                }
+                p
+            }
+            None => return Ok(toml::Value::Table(toml::value::Table::new())),
+        },
+    };
+
+    let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn apply_env(
+    value: &mut toml::Value,
+    defaults: &toml::Value,
+    vars: impl Iterator<Item = (String, String)>,
+    errors: &mut Vec<String>,
+) {
+    let sections = defaults.as_table().unwrap();
+
+    for (name, env_value) in vars {
+        let key = match name.strip_prefix(ENV_PREFIX) {
+            Some(k) => k.to_lowercase(),
+            None => continue,
+        };
+
+        let section_name = sections
+            .keys()
+            .filter(|s| key.starts_with(&format!("{}_", s)))
+            .max_by_key(|s| s.len());
+
+        // Variables of other tools may share the prefix, so they are only reported
+        let section_name = match section_name {
+            Some(s) => s,
+            None => {
+                warn!("{}: unknown config section, ignored", name);
+                continue;
+            }
+        };
+
+        let field = key[section_name.len() + 1..].to_string();
+
+        // String fields are taken as is, others are parsed as TOML values
+        let field_value = match sections[section_name].get(&field) {
+            Some(toml::Value::String(_)) => toml::Value::String(env_value),
+            Some(_) => parse_env_value(env_value),
+            None if OPTIONAL_FIELDS.contains(&format!("{}.{}", section_name, field).as_str()) => {
+                toml::Value::String(env_value)
+            }
+            None => {
+                warn!("{}: unknown config field, ignored", name);
+                continue;
+            }
+        };
+
+        let section = value
+            .as_table_mut()
+            .unwrap()
+            .entry(section_name.clone())
+            .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+
+        match section.as_table_mut() {
+            Some(t) => {
+                t.insert(field, field_value);
+            }
+            None => errors.push(format!("{}: must be a table", section_name)),
+        }
+    }
+}
+
+fn parse_env_value(env_value: String) -> toml::Value {
+    match toml::from_str::<toml::Value>(&format!("value = {}", env_value)) {
+        Ok(toml::Value::Table(mut t)) => t.remove("value").unwrap(),
+        _ => toml::Value::String(env_value),
+    }
+}
+
+fn section<T: DeserializeOwned + Default>(
+    value: &toml::Value,
+    name: &str,
+    errors: &mut Vec<String>,
+) -> T {
+    match value.get(name) {
+        Some(v) => v.clone().try_into().unwrap_or_else(|e| {
+            errors.push(format!("{}: {}", name, e));
+            T::default()
+        }),
+        None => T::default(),
+    }
+}
+
+fn diff(old_config: &Config, new_config: &Config) -> Vec<String> {
+    let old_fields = flatten(&toml::Value::try_from(old_config).unwrap());
+    let new_fields = flatten(&toml::Value::try_from(new_config).unwrap());
+
+    let mut paths = old_fields
+        .keys()
+        .chain(new_fields.keys())
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+
+    let show = |path: &str, value: Option<&String>| match value {
+        Some(_) if SECRET_FIELDS.contains(&path) => "***".to_string(),
+        Some(v) => v.clone(),
+        None => "none".to_string(),
+    };
+
+    paths
+        .into_iter()
+        .filter(|p| old_fields.get(*p) != new_fields.get(*p))
+        .map(|p| {
+            let restart = if RESTART_SECTIONS.iter().any(|s| p.starts_with(s)) {
+                " (restart required)"
+            } else {
+                ""
+            };
+            format!(
+                "{}: {} -> {}{}",
+                p,
+                show(p, old_fields.get(p)),
+                show(p, new_fields.get(p)),
+                restart
+            )
+        })
+        .collect()
+}
+
+fn flatten(value: &toml::Value) -> BTreeMap<String, String> {
+    fn walk(prefix: &str, value: &toml::Value, fields: &mut BTreeMap<String, String>) {
+        match value {
+            toml::Value::Table(t) => {
+                for (k, v) in t {
+                    let path = if prefix.is_empty() {
+                        k.clone()
+                    } else {
+                        format!("{}.{}", prefix, k)
+                    };
+                    walk(&path, v, fields);
+                }
+            }
+            v => {
+                fields.insert(prefix.to_string(), v.to_string());
+            }
+        }
+    }
+
+    let mut fields = BTreeMap::new();
+    walk("", value, &mut fields);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(vars: &[(&str, &str)]) -> (toml::Value, Vec<String>) {
+        let defaults = toml::Value::try_from(Config::default()).unwrap();
+        let mut value = toml::Value::Table(toml::value::Table::new());
+        let mut errors = Vec::new();
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        apply_env(&mut value, &defaults, vars, &mut errors);
+        (value, errors)
+    }
+
+    #[test]
+    fn env_values_are_parsed_as_toml() {
+        assert_eq!(parse_env_value("8080".into()), toml::Value::Integer(8080));
+        assert_eq!(parse_env_value("true".into()), toml::Value::Boolean(true));
+        assert_eq!(
+            parse_env_value(r#"["a", "b"]"#.into()),
+            toml::Value::Array(vec!["a".into(), "b".into()])
+        );
+        assert_eq!(
+            parse_env_value(r#"{ burst = 5, per_minute = 10 }"#.into())["burst"],
+            toml::Value::Integer(5)
+        );
+    }
+
+    #[test]
+    fn malformed_env_values_are_kept_as_strings() {
+        assert_eq!(parse_env_value("abc".into()), toml::Value::from("abc"));
+        assert_eq!(parse_env_value("[1,".into()), toml::Value::from("[1,"));
+        assert_eq!(parse_env_value("".into()), toml::Value::from(""));
+    }
+
+    #[test]
+    fn env_overrides_fields_by_type() {
+        let (value, errors) = apply(&[
+            ("OCEAN_SERVER_PORT", "8080"),
+            ("OCEAN_POSTGRES_PASSWORD", "123"),
+            ("OCEAN_TELEGRAM_BOT_TOKEN", "1:abc"),
+            ("OCEAN_RATE_LIMIT_TRUSTED_PROXIES", r#"["10.0.0.1"]"#),
+        ]);
+        assert!(errors.is_empty());
+        assert_eq!(value["server"]["port"], toml::Value::Integer(8080));
+        // String fields are not parsed, so digits stay a string
+        assert_eq!(value["postgres"]["password"], toml::Value::from("123"));
+        // Section names may contain underscores too
+        assert_eq!(value["telegram_bot"]["token"], toml::Value::from("1:abc"));
+        assert_eq!(
+            value["rate_limit"]["trusted_proxies"],
+            toml::Value::Array(vec!["10.0.0.1".into()])
+        );
+    }
+
+    #[test]
+    fn optional_fields_are_taken_as_strings() {
+        let (value, errors) = apply(&[
+            ("OCEAN_POSTGRES_URL", "postgres://localhost/ocean"),
+            ("OCEAN_POSTGRES_SSL_MODE", "require"),
+        ]);
+        assert!(errors.is_empty());
+        assert_eq!(
+            value["postgres"]["url"],
+            toml::Value::from("postgres://localhost/ocean")
+        );
+        assert_eq!(value["postgres"]["ssl_mode"], toml::Value::from("require"));
+    }
+
+    #[test]
+    fn unknown_and_foreign_variables_are_ignored() {
+        let (value, errors) = apply(&[
+            ("OCEAN_UNKNOWN_FIELD", "1"),
+            ("OCEAN_SERVER_UNKNOWN", "1"),
+            ("OCEAN_SERVER", "1"),
+            ("HOME", "/root"),
+        ]);
+        assert!(errors.is_empty());
+        assert_eq!(value, toml::Value::Table(toml::value::Table::new()));
+    }
+
+    #[test]
+    fn non_table_section_is_reported() {
+        let defaults = toml::Value::try_from(Config::default()).unwrap();
+        let mut value = toml::from_str::<toml::Value>("server = 1").unwrap();
+        let mut errors = Vec::new();
+        let vars = vec![("OCEAN_SERVER_PORT".to_string(), "1".to_string())];
+        apply_env(&mut value, &defaults, vars.into_iter(), &mut errors);
+        assert_eq!(errors, vec!["server: must be a table"]);
     }
 }
@@ -1,4 +1,6 @@
+use super::validation::{Validate, Validator};
 use super::*;
+use crate::api;
 use crate::model::comment;
 use chrono::prelude::*;
 use chrono::NaiveDateTime;
@@ -6,9 +8,37 @@ use diesel::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
 
+const MAX_MESSAGE_LENGTH: usize = 5000;
+const MAX_LIMIT: i64 = 100;
+
+fn validate_message(v: &mut Validator, value: &str) {
+    v.not_empty("message", value)
+        .max_length("message", value, MAX_MESSAGE_LENGTH);
+}
+
 // comment.create
-pub fn create(data: RequestData) -> RequestResult {
-    let new_comment = serde_json::from_value::<comment::NewComment>(data.params.unwrap())?;
+pub fn create(mut data: RequestData) -> RequestResult {
+    #[derive(Deserialize)]
+    struct Req {
+        mandela_id: i32,
+        message: String,
+    }
+
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.positive("mandela_id", self.mandela_id.into());
+            validate_message(v, &self.message);
+        }
+    }
+
+    let comment_user_id = data.user_id()?;
+    let req = data.parse::<Req>()?;
+
+    let new_comment = comment::NewComment {
+        mandela_id: req.mandela_id,
+        user_id: comment_user_id,
+        message: req.message,
+    };
 
     use crate::model::schema::comments::dsl::*;
 
@@ -20,7 +50,7 @@ pub fn create(data: RequestData) -> RequestResult {
 }
 
 // comment.getAll
-pub fn get_all(data: RequestData) -> RequestResult {
+pub fn get_all(mut data: RequestData) -> RequestResult {
     use crate::model::schema::comments;
     use crate::model::schema::comments::dsl::*;
     use crate::model::schema::users;
@@ -33,7 +63,15 @@ pub fn get_all(data: RequestData) -> RequestResult {
         limit: i64,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.positive("mandela_id", self.mandela_id.into())
+                .non_negative("offset", self.offset)
+                .range("limit", self.limit, 1, MAX_LIMIT);
+        }
+    }
+
+    let req = data.parse::<Req>()?;
 
     let list = comments
         .inner_join(users)
@@ -72,7 +110,7 @@ pub fn get_all(data: RequestData) -> RequestResult {
 }
 
 // comment.update
-pub fn update(data: RequestData) -> RequestResult {
+pub fn update(mut data: RequestData) -> RequestResult {
     use crate::model::schema::comments;
     use crate::model::schema::comments::dsl::*;
 
@@ -82,7 +120,16 @@ pub fn update(data: RequestData) -> RequestResult {
         message: String,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.positive("id", self.id.into());
+            validate_message(v, &self.message);
+        }
+    }
+
+    let req = data.parse::<Req>()?;
+    let owner_id = find_owner(&data.db.conn, req.id)?;
+    data.require_owner(owner_id, "comment.update.any")?;
 
     #[derive(AsChangeset)]
     #[table_name = "comments"]
@@ -104,10 +151,42 @@ pub fn update(data: RequestData) -> RequestResult {
 }
 
 // comment.delete
-pub fn delete(data: RequestData) -> RequestResult {
+pub fn delete(mut data: RequestData) -> RequestResult {
     use crate::model::schema::comments::dsl::*;
-    let comment_id = data.params.unwrap()["id"].as_i64().unwrap() as i32;
 
-    diesel::delete(comments.filter(id.eq(comment_id))).execute(&data.db.conn)?;
+    #[derive(Deserialize)]
+    struct Req {
+        id: i32,
+    }
+
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.positive("id", self.id.into());
+        }
+    }
+
+    let req = data.parse::<Req>()?;
+    let owner_id = find_owner(&data.db.conn, req.id)?;
+    data.require_owner(owner_id, "comment.delete.any")?;
+
+    diesel::delete(comments.filter(id.eq(req.id))).execute(&data.db.conn)?;
     Ok(None)
 }
+
+fn find_owner(
+    conn: &db::TimedConnection,
+    comment_id: i32,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    use crate::model::schema::comments::dsl::*;
+
+    let owner_id = comments
+        .select(user_id)
+        .filter(id.eq(comment_id))
+        .first::<i32>(conn)
+        .optional()?;
+
+    match owner_id {
+        Some(i) => Ok(i),
+        None => Err(api::Failure::NotFound(Some("id".into())).into()),
+    }
+}
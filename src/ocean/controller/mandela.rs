@@ -1,5 +1,9 @@
+use super::validation::{Validate, Validator};
 use super::*;
-use crate::model::mandela;
+use crate::api;
+use crate::json_rpc;
+use crate::model::mandela::{self, TitleMode};
+use crate::model::variant::Variant;
 use crate::telegram_bot;
 use chrono::prelude::*;
 use chrono::NaiveDateTime;
@@ -9,6 +13,101 @@ use diesel::sql_types::Int8;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::json;
+use std::convert::TryFrom;
+
+const MAX_TITLE_LENGTH: usize = 255;
+const MAX_DESCRIPTION_LENGTH: usize = 10000;
+const MAX_LIMIT: i64 = 100;
+
+// Filter of mandela.getAll
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(try_from = "Variant")]
+enum Show {
+    All = 0,
+    New = 1,
+    Mine = 2,
+    Category = 3,
+}
+
+impl TryFrom<Variant> for Show {
+    type Error = String;
+
+    fn try_from(v: Variant) -> Result<Self, Self::Error> {
+        v.resolve(
+            "filter",
+            &[
+                (0, "all", Show::All),
+                (1, "new", Show::New),
+                (2, "mine", Show::Mine),
+                (3, "category", Show::Category),
+            ],
+        )
+    }
+}
+
+// Order of mandela.getAll: by creation or by the last comment
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(try_from = "Variant")]
+enum Sort {
+    Mandela = 0,
+    Comment = 1,
+}
+
+impl TryFrom<Variant> for Sort {
+    type Error = String;
+
+    fn try_from(v: Variant) -> Result<Self, Self::Error> {
+        v.resolve(
+            "sort",
+            &[(0, "mandela", Sort::Mandela), (1, "comment", Sort::Comment)],
+        )
+    }
+}
+
+// Editable fields of mandela.create and mandela.update
+#[derive(Deserialize)]
+struct Content {
+    title_mode: TitleMode,
+    title: String,
+    what: String,
+    before: String,
+    after: String,
+    description: String,
+    images: serde_json::Value,
+    videos: serde_json::Value,
+    links: serde_json::Value,
+    categories: Vec<i16>,
+}
+
+impl Validate for Content {
+    fn validate(&self, v: &mut Validator) {
+        match self.title_mode {
+            TitleMode::Simple => {
+                v.not_empty("title", &self.title);
+            }
+            TitleMode::Complex => {
+                v.not_empty("what", &self.what)
+                    .not_empty("before", &self.before)
+                    .not_empty("after", &self.after);
+            }
+        }
+
+        v.max_length("title", &self.title, MAX_TITLE_LENGTH)
+            .max_length("what", &self.what, MAX_TITLE_LENGTH)
+            .max_length("before", &self.before, MAX_TITLE_LENGTH)
+            .max_length("after", &self.after, MAX_TITLE_LENGTH)
+            .max_length("description", &self.description, MAX_DESCRIPTION_LENGTH)
+            .array("images", &self.images)
+            .array("videos", &self.videos)
+            .array("links", &self.links);
+
+        v.check(
+            self.categories.iter().all(|&c| c >= 0),
+            "categories",
+            "no_negative_items",
+        );
+    }
+}
 
 #[derive(QueryableByName, Serialize)]
 struct Votes {
@@ -19,7 +118,7 @@ struct Votes {
 }
 
 fn update_categories(
-    conn: &PgConnection,
+    conn: &db::TimedConnection,
     mandela_id: i32,
     category_numbers: Vec<i16>,
 ) -> RequestResult {
@@ -73,28 +172,14 @@ fn update_categories(
 }
 
 // mandela.create
-pub fn create(data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        title_mode: i32,
-        title: String,
-        what: String,
-        before: String,
-        after: String,
-        description: String,
-        images: serde_json::Value,
-        videos: serde_json::Value,
-        links: serde_json::Value,
-        categories: serde_json::Value,
-        user_id: i32,
-    }
-
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+pub fn create(mut data: RequestData) -> RequestResult {
+    let mandela_user_id = data.user_id()?;
+    let req = data.parse::<Content>()?;
 
     use crate::model::schema::mandels::dsl::*;
 
     let new_mandela = mandela::NewMandela {
-        title_mode: req.title_mode,
+        title_mode: req.title_mode.into(),
         title: req.title,
         what: req.what,
         before: req.before,
@@ -103,32 +188,42 @@ pub fn create(data: RequestData) -> RequestResult {
         images: req.images,
         videos: req.videos,
         links: req.links,
-        user_id: req.user_id,
+        user_id: mandela_user_id,
     };
     let mandela_id = diesel::insert_into(mandels)
         .values(&new_mandela)
         .returning(id)
         .get_result::<i32>(&data.db.conn)?;
 
-    let category_numbers: Vec<i16> = serde_json::from_value(req.categories).unwrap();
-    update_categories(&data.db.conn, mandela_id, category_numbers)?;
+    update_categories(&data.db.conn, mandela_id, req.categories)?;
 
-    send_telegram_notify(&new_mandela, mandela_id, &data.db);
+    send_telegram_notify(&new_mandela, req.title_mode, mandela_id, &data.db);
 
     let result = json!({ "id": mandela_id });
     Ok(Some(result))
 }
 
-fn send_telegram_notify(mandela: &mandela::NewMandela, mandela_id: i32, db: &db::Db) {
-    const TITLE_MODE_SIMPLE: i32 = 0;
-    const TITLE_MODE_COMPLEX: i32 = 1;
+fn find_owner(conn: &db::TimedConnection, mandela_id: i32) -> QueryResult<Option<i32>> {
+    use crate::model::schema::mandels::dsl::*;
+
+    mandels
+        .select(user_id)
+        .filter(id.eq(mandela_id))
+        .first::<i32>(conn)
+        .optional()
+}
 
-    let title = if mandela.title_mode == TITLE_MODE_SIMPLE {
-        mandela.title.clone()
-    } else if mandela.title_mode == TITLE_MODE_COMPLEX {
-        mandela.what.clone() + ": " + &mandela.before + " / " + &mandela.after
-    } else {
-        "Неизвестная мандела".into()
+fn send_telegram_notify(
+    mandela: &mandela::NewMandela,
+    title_mode: TitleMode,
+    mandela_id: i32,
+    db: &db::Db,
+) {
+    let title = match title_mode {
+        TitleMode::Simple => mandela.title.clone(),
+        TitleMode::Complex => {
+            mandela.what.clone() + ": " + &mandela.before + " / " + &mandela.after
+        }
     };
 
     // TODO: Take domen name from settings
@@ -141,38 +236,41 @@ fn send_telegram_notify(mandela: &mandela::NewMandela, mandela_id: i32, db: &db:
 }
 
 // mandela.update
-pub fn update(data: RequestData) -> RequestResult {
+pub fn update(mut data: RequestData) -> RequestResult {
     use crate::model::schema::mandels;
     use crate::model::schema::mandels::dsl::*;
     #[derive(Deserialize)]
     struct Req {
         id: i32,
-        title_mode: i32,
-        title: String,
-        what: String,
-        before: String,
-        after: String,
-        description: String,
-        images: serde_json::Value,
-        videos: serde_json::Value,
-        links: serde_json::Value,
-        categories: serde_json::Value,
-        user_id: i32,
+        #[serde(flatten)]
+        content: Content,
+    }
+
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.positive("id", self.id.into());
+            self.content.validate(v);
+        }
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    let req = data.parse::<Req>()?;
+    let content = req.content;
+
+    match find_owner(&data.db.conn, req.id)? {
+        Some(owner_id) => data.require_owner(owner_id, "mandela.update.any")?,
+        None => return Err(api::Failure::NotFound(Some("id".into())).into()),
+    }
 
     let update_mandela = mandela::UpdateMandela {
-        title_mode: req.title_mode,
-        title: req.title,
-        what: req.what,
-        before: req.before,
-        after: req.after,
-        description: req.description,
-        images: req.images,
-        videos: req.videos,
-        links: req.links,
-        user_id: req.user_id,
+        title_mode: content.title_mode.into(),
+        title: content.title,
+        what: content.what,
+        before: content.before,
+        after: content.after,
+        description: content.description,
+        images: content.images,
+        videos: content.videos,
+        links: content.links,
         update_ts: Utc::now().naive_utc(),
     };
 
@@ -180,13 +278,12 @@ pub fn update(data: RequestData) -> RequestResult {
         .set(&update_mandela)
         .execute(&data.db.conn)?;
 
-    let category_numbers: Vec<i16> = serde_json::from_value(req.categories).unwrap();
-    update_categories(&data.db.conn, req.id, category_numbers)?;
+    update_categories(&data.db.conn, req.id, content.categories)?;
 
     Ok(None)
 }
 
-fn get_poll(db: &db::Db, mandela_id: i32) -> Vec<Votes> {
+fn get_poll(db: &db::Db, mandela_id: i32) -> QueryResult<Vec<Votes>> {
     use diesel::dsl::*;
     use diesel::sql_types::Int4;
 
@@ -198,11 +295,10 @@ fn get_poll(db: &db::Db, mandela_id: i32) -> Vec<Votes> {
     )
     .bind::<Int4, _>(mandela_id)
     .load::<Votes>(&db.conn)
-    .unwrap()
 }
 
 // mandela.getOne
-pub fn get_one(data: RequestData) -> RequestResult {
+pub fn get_one(mut data: RequestData) -> RequestResult {
     use crate::model::schema::mandels;
     use crate::model::schema::mandels::dsl::*;
     use crate::model::schema::marks;
@@ -213,11 +309,17 @@ pub fn get_one(data: RequestData) -> RequestResult {
     #[derive(Deserialize)]
     struct Req {
         id: i32,
-        user_id: Option<i32>,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
-    let mark_user_id = if let Some(i) = req.user_id { i } else { 0 };
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.positive("id", self.id.into());
+        }
+    }
+
+    let req_user_id = data.user_id().ok();
+    let req = data.parse::<Req>()?;
+    let mark_user_id = if let Some(i) = req_user_id { i } else { 0 };
 
     #[derive(Queryable, Serialize)]
     pub struct Mandela {
@@ -271,7 +373,7 @@ pub fn get_one(data: RequestData) -> RequestResult {
     let mut mandela_vote: Option<i16> = None;
     let mut mandela_votes: Option<Vec<Votes>> = None;
 
-    if let Some(i) = req.user_id {
+    if let Some(i) = req_user_id {
         mandela_vote = votes
             .select(votes::vote)
             .filter(votes::mandela_id.eq(req.id).and(votes::user_id.eq(i)))
@@ -279,7 +381,7 @@ pub fn get_one(data: RequestData) -> RequestResult {
             .optional()?;
 
         if let Some(_) = mandela_vote {
-            let votes_count = get_poll(&data.db, req.id);
+            let votes_count = get_poll(&data.db, req.id)?;
             mandela_votes = Some(votes_count);
         }
     };
@@ -312,7 +414,7 @@ pub fn get_one(data: RequestData) -> RequestResult {
 }
 
 // mandela.getAll
-pub fn get_all(data: RequestData) -> RequestResult {
+pub fn get_all(mut data: RequestData) -> RequestResult {
     use crate::model::schema::categories;
     use crate::model::schema::categories::dsl::*;
     use crate::model::schema::comments;
@@ -329,13 +431,25 @@ pub fn get_all(data: RequestData) -> RequestResult {
     struct Req {
         offset: i64,
         limit: i64,
-        user_id: Option<i32>,
-        filter: Option<i8>,
+        filter: Option<Show>,
         category: Option<i16>,
-        sort: i8,
+        sort: Sort,
+    }
+
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.non_negative("offset", self.offset)
+                .range("limit", self.limit, 1, MAX_LIMIT)
+                .check(
+                    self.filter != Some(Show::Category) || self.category.is_some(),
+                    "category",
+                    "required_by_filter",
+                );
+        }
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    let auth_user_id = data.user_id().ok();
+    let req = data.parse::<Req>()?;
 
     #[derive(Queryable, Serialize)]
     struct MandelaResp {
@@ -352,17 +466,11 @@ pub fn get_all(data: RequestData) -> RequestResult {
         mark_ts: Option<NaiveDateTime>,
     }
 
-    let req_user_id = if let Some(i) = req.user_id { i } else { 0 };
-    const SHOW_ALL: i8 = 0;
-    const SHOW_NEW: i8 = 1;
-    const SHOW_MINE: i8 = 2;
-    const SHOW_CATEGORY: i8 = 3;
+    let req_user_id = if let Some(i) = auth_user_id { i } else { 0 };
 
-    let filter = if let Some(i) = req.filter {
-        i
-    } else {
-        SHOW_ALL
-    };
+    let filter = req.filter.unwrap_or(Show::All);
+    // Required by validation for the category filter
+    let category = req.category.unwrap_or_default();
 
     let mut query = mandels
         .inner_join(users)
@@ -388,24 +496,19 @@ pub fn get_all(data: RequestData) -> RequestResult {
         ))
         .into_boxed();
 
-    if filter == SHOW_NEW {
-        query = query.filter(marks::create_ts.is_null())
-    } else if filter == SHOW_MINE {
-        query = query.filter(mandels::user_id.eq(req_user_id))
-    } else if filter == SHOW_CATEGORY {
-        query = query.filter(categories::number.eq(req.category.unwrap()));
+    match filter {
+        Show::All => {}
+        Show::New => query = query.filter(marks::create_ts.is_null()),
+        Show::Mine => query = query.filter(mandels::user_id.eq(req_user_id)),
+        Show::Category => query = query.filter(categories::number.eq(category)),
     }
 
     query = query.group_by((mandels::id, users::name, users::id, marks::create_ts));
 
-    const SORT_MANDELA: i8 = 0;
-    const SORT_COMMENT: i8 = 1;
-
-    if req.sort == SORT_MANDELA {
-        query = query.order(mandels::id.desc());
-    } else if req.sort == SORT_COMMENT {
-        query = query.order(max(comments::create_ts).desc().nulls_last());
-    }
+    query = match req.sort {
+        Sort::Mandela => query.order(mandels::id.desc()),
+        Sort::Comment => query.order(max(comments::create_ts).desc().nulls_last()),
+    };
 
     let mut list = query
         .offset(req.offset)
@@ -425,22 +528,22 @@ pub fn get_all(data: RequestData) -> RequestResult {
     let mut mine_count = 0;
     let mut category_count = 0;
 
-    if let Some(i) = req.user_id {
+    if let Some(i) = auth_user_id {
         let mark_count: i64 = marks
             .select(count_star())
             .filter(marks::user_id.eq(i))
             .first(&data.db.conn)?;
         new_count = total_count - mark_count;
-        if filter == SHOW_MINE {
+        if filter == Show::Mine {
             mine_count = mandels
                 .select(count_star())
                 .filter(mandels::user_id.eq(i))
                 .first(&data.db.conn)?;
-        } else if filter == SHOW_CATEGORY {
+        } else if filter == Show::Category {
             category_count = mandels
                 .select(count_star())
                 .inner_join(categories)
-                .filter(number.eq(req.category.unwrap()))
+                .filter(number.eq(category))
                 .first(&data.db.conn)?;
         }
     }
@@ -467,29 +570,82 @@ pub fn get_all(data: RequestData) -> RequestResult {
 }
 
 // mandela.delete
-pub fn delete(data: RequestData) -> RequestResult {
+pub fn delete(mut data: RequestData) -> RequestResult {
     #[derive(Deserialize)]
     struct Req {
         id: Vec<i32>,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.check(!self.id.is_empty(), "id", "not_empty");
+        }
+    }
+
+    let req = data.parse::<Req>()?;
 
     use crate::model::schema::mandels::dsl::*;
 
-    diesel::delete(mandels.filter(id.eq_any(req.id))).execute(&data.db.conn)?;
-    Ok(None)
+    #[derive(Serialize)]
+    struct DeleteResult {
+        id: i32,
+        deleted: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<json_rpc::Error>,
+    }
+
+    // A database error rolls back the whole batch, so nothing is deleted
+    // without being reported
+    let results = data
+        .db
+        .conn
+        .transaction::<_, Box<dyn std::error::Error>, _>(|| {
+            let mut results = Vec::new();
+
+            for &mandela_id in &req.id {
+                let failure = match find_owner(&data.db.conn, mandela_id)? {
+                    Some(owner_id) => {
+                        if data.is_owner(owner_id, "mandela.delete.any")? {
+                            diesel::delete(mandels.filter(id.eq(mandela_id)))
+                                .execute(&data.db.conn)?;
+                            None
+                        } else {
+                            Some(api::Failure::Forbidden)
+                        }
+                    }
+                    None => Some(api::Failure::NotFound(None)),
+                };
+
+                results.push(DeleteResult {
+                    id: mandela_id,
+                    deleted: failure.is_none(),
+                    error: failure
+                        .map(|f| json_rpc::Error::from_api_error(&f.into(), &data.client.locale)),
+                });
+            }
+
+            Ok(results)
+        })?;
+
+    let result = serde_json::to_value(&results)?;
+    Ok(Some(result))
 }
 
 // mandela.mark
-pub fn mark(data: RequestData) -> RequestResult {
+pub fn mark(mut data: RequestData) -> RequestResult {
     #[derive(Deserialize)]
     struct Req {
         id: i32,
-        user_id: i32,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.positive("id", self.id.into());
+        }
+    }
+
+    let mark_user_id = data.user_id()?;
+    let req = data.parse::<Req>()?;
 
     use crate::model::schema::marks;
     use crate::model::schema::marks::dsl::*;
@@ -503,7 +659,7 @@ pub fn mark(data: RequestData) -> RequestResult {
 
     let new_mark = NewMark {
         mandela_id: req.id,
-        user_id: req.user_id,
+        user_id: mark_user_id,
     };
 
     diesel::insert_into(marks)
@@ -513,15 +669,22 @@ pub fn mark(data: RequestData) -> RequestResult {
 }
 
 // mandela.vote
-pub fn vote(data: RequestData) -> RequestResult {
+pub fn vote(mut data: RequestData) -> RequestResult {
     #[derive(Deserialize)]
     struct Req {
         id: i32,
-        user_id: i32,
         vote: i16,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            // Vote values are defined by the client and stored as is
+            v.positive("id", self.id.into());
+        }
+    }
+
+    let vote_user_id = data.user_id()?;
+    let req = data.parse::<Req>()?;
 
     #[derive(Insertable, AsChangeset)]
     #[table_name = "votes"]
@@ -533,7 +696,7 @@ pub fn vote(data: RequestData) -> RequestResult {
 
     let new_vote = NewVote {
         mandela_id: req.id,
-        user_id: req.user_id,
+        user_id: vote_user_id,
         vote: req.vote,
     };
 
@@ -542,7 +705,7 @@ pub fn vote(data: RequestData) -> RequestResult {
 
     let vote_id = votes
         .select(id)
-        .filter(mandela_id.eq(req.id).and(user_id.eq(req.user_id)))
+        .filter(mandela_id.eq(req.id).and(user_id.eq(vote_user_id)))
         .first::<i32>(&data.db.conn)
         .optional()?;
 
@@ -556,7 +719,7 @@ pub fn vote(data: RequestData) -> RequestResult {
             .execute(&data.db.conn)?;
     }
 
-    let votes_count = get_poll(&data.db, req.id);
+    let votes_count = get_poll(&data.db, req.id)?;
     let result = serde_json::to_value(&votes_count)?;
     Ok(Some(result))
 }
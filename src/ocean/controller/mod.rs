@@ -1,21 +1,96 @@
+use crate::api;
 use crate::db;
+use crate::model::session::Session;
+use crate::model::user::User;
+use crate::model::user_group::UserGroup;
+use serde::de::DeserializeOwned;
+use validation::Validate;
 
 pub mod comment;
 pub mod mandela;
+pub mod permission;
 pub mod rating;
 pub mod search;
 pub mod user;
+pub mod validation;
 
 pub type RequestResult = Result<Option<serde_json::Value>, Box<dyn std::error::Error>>;
 pub type RequestHandler = fn(RequestData) -> RequestResult;
 
+// Caller of a request as resolved by the router
+pub struct Client {
+    pub session: Option<Session>,
+    pub user: Option<User>,
+    pub group: UserGroup,
+    pub user_agent: Option<String>,
+    pub locale: api::Locale,
+}
+
 pub struct RequestData {
     db: db::Db,
     params: Option<serde_json::Value>,
+    client: Client,
 }
 
 impl RequestData {
-    pub fn new(db: db::Db, params: Option<serde_json::Value>) -> Self {
-        Self { db, params }
+    pub fn new(db: db::Db, params: Option<serde_json::Value>, client: Client) -> Self {
+        Self { db, params, client }
+    }
+
+    // Takes required parameters of the request and validates them
+    fn parse<T: DeserializeOwned + Validate>(&mut self) -> Result<T, Box<dyn std::error::Error>> {
+        match self.params.take() {
+            Some(p) => validation::parse::<T>(p),
+            None => Err(api::make_error_data(
+                api::error::PARAMETER_NOT_FOUND,
+                "params",
+            )),
+        }
+    }
+
+    // Identifier of the user authenticated by session
+    fn user_id(&self) -> Result<i32, Box<dyn std::error::Error>> {
+        match &self.client.user {
+            Some(u) => Ok(u.id),
+            None => Err(api::Failure::Unauthenticated.into()),
+        }
+    }
+
+    fn is_permitted(&self, action: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(permission::is_permitted(
+            &self.db.conn,
+            self.client.group.id,
+            action,
+        )?)
+    }
+
+    // Checks an action from the permission matrix for the group of the caller
+    fn require_permission(&self, action: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.is_permitted(action)? {
+            Ok(())
+        } else {
+            Err(api::Failure::PermissionDenied(Some(action.to_string())).into())
+        }
+    }
+
+    // Only the author of a record or a moderator holding `moderator_action` may change it
+    fn is_owner(
+        &self,
+        owner_id: i32,
+        moderator_action: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.user_id()? == owner_id || self.is_permitted(moderator_action)?)
+    }
+
+    fn require_owner(
+        &self,
+        owner_id: i32,
+        moderator_action: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.is_owner(owner_id, moderator_action)? {
+            Ok(())
+        } else {
+            Err(api::Failure::Forbidden.into())
+        }
     }
 }
@@ -0,0 +1,78 @@
+use super::validation::Validate;
+use super::*;
+use diesel::dsl::exists;
+use diesel::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub fn is_permitted(
+    conn: &db::TimedConnection,
+    user_group_id: i32,
+    permission: &str,
+) -> QueryResult<bool> {
+    use crate::model::schema::permissions::dsl::*;
+
+    diesel::select(exists(
+        permissions.filter(group_id.eq(user_group_id).and(action.eq(permission))),
+    ))
+    .get_result(conn)
+}
+
+// permission.getAll
+pub fn get_all(data: RequestData) -> RequestResult {
+    use crate::model::schema::permissions;
+    use crate::model::schema::permissions::dsl::*;
+    use crate::model::schema::user_groups;
+    use crate::model::schema::user_groups::dsl::*;
+
+    #[derive(Queryable, Serialize)]
+    struct Permission {
+        code: String,
+        action: String,
+    }
+
+    let list = permissions
+        .inner_join(user_groups)
+        .select((code, action))
+        .order((user_groups::id.asc(), permissions::id.asc()))
+        .load::<Permission>(&data.db.conn)?;
+
+    let result = serde_json::to_value(&list)?;
+    Ok(Some(result))
+}
+
+// permission.update
+pub fn update(mut data: RequestData) -> RequestResult {
+    use crate::model::schema::permissions;
+    use crate::model::schema::permissions::dsl::*;
+
+    #[derive(Deserialize)]
+    struct Req {
+        code: String,
+        action: String,
+        allowed: bool,
+    }
+
+    impl Validate for Req {}
+
+    let req = data.parse::<Req>()?;
+    let group = user::find_group(&data.db.conn, &req.code)?;
+
+    if req.allowed {
+        diesel::insert_into(permissions)
+            .values((permissions::group_id.eq(group.id), action.eq(req.action)))
+            .on_conflict_do_nothing()
+            .execute(&data.db.conn)?;
+    } else {
+        diesel::delete(
+            permissions.filter(
+                permissions::group_id
+                    .eq(group.id)
+                    .and(action.eq(req.action)),
+            ),
+        )
+        .execute(&data.db.conn)?;
+    }
+
+    Ok(None)
+}
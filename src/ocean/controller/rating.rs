@@ -1,15 +1,18 @@
+use super::validation::Validate;
 use super::*;
 use serde::Deserialize;
 use serde::Serialize;
 
 // rating.getMandels
-pub fn get_mandels(data: RequestData) -> RequestResult {
+pub fn get_mandels(mut data: RequestData) -> RequestResult {
     #[derive(Deserialize)]
     struct Req {
         vote: i16,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {}
+
+    let req = data.parse::<Req>()?;
 
     use diesel::prelude::*;
     use diesel::sql_types::Int2;
@@ -1,16 +1,22 @@
+use super::validation::{Validate, Validator};
 use super::*;
 use diesel::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
 
+const MAX_CONTENT_LENGTH: usize = 255;
+
 // search.getById
-pub fn get_by_id(data: RequestData) -> RequestResult {
+pub fn get_by_id(mut data: RequestData) -> RequestResult {
     #[derive(Deserialize)]
     struct Req {
         id: Option<i32>,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    // Ids which are not positive find nothing
+    impl Validate for Req {}
+
+    let req = data.parse::<Req>()?;
 
     let search_id;
 
@@ -50,7 +56,7 @@ pub fn get_by_id(data: RequestData) -> RequestResult {
 }
 
 // search.getByContent
-pub fn get_by_content(data: RequestData) -> RequestResult {
+pub fn get_by_content(mut data: RequestData) -> RequestResult {
     #[derive(Deserialize)]
     struct Req {
         content: String,
@@ -58,7 +64,13 @@ pub fn get_by_content(data: RequestData) -> RequestResult {
         search_description: bool,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.max_length("content", &self.content, MAX_CONTENT_LENGTH);
+        }
+    }
+
+    let req = data.parse::<Req>()?;
 
     if req.content.is_empty() || !(req.search_title || req.search_description) {
         let data = r#"[]"#;
@@ -1,34 +1,62 @@
+use super::validation::{self, Validate, Validator};
 use super::*;
 use crate::api;
+use crate::model::session;
 use crate::model::user;
 use crate::model::user_group;
 use chrono::prelude::*;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::Deserialize;
+use serde::Serialize;
 use serde_json::json;
 
+const MAX_NAME_LENGTH: usize = 64;
+// bcrypt ignores bytes after the 72nd
+const MAX_TOKEN_LENGTH: usize = 72;
+
+fn validate_token(v: &mut Validator, value: &str) {
+    v.not_empty("token", value)
+        .max_bytes("token", value, MAX_TOKEN_LENGTH);
+}
+
+fn validate_name(v: &mut Validator, value: &str) {
+    v.max_length("name", value, MAX_NAME_LENGTH);
+}
+
 // user.create
-pub fn create(data: RequestData) -> RequestResult {
-    use crate::model::schema::user_groups::dsl::*;
+pub fn create(mut data: RequestData) -> RequestResult {
     use crate::model::schema::users;
     use crate::model::schema::users::dsl::*;
     #[derive(Deserialize)]
     struct Req {
         name: Option<String>,
+        token: String,
         code: String,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            if let Some(n) = &self.name {
+                validate_name(v, n);
+            }
+            validate_token(v, &self.token);
+            v.not_empty("code", &self.code);
+        }
+    }
+
+    let req = data.parse::<Req>()?;
+
+    if !is_default_group(&req.code) {
+        data.require_permission("user.update.any")?;
+    }
 
-    let groups = user_groups
-        .filter(code.eq(req.code))
-        .limit(1)
-        .load::<user_group::UserGroup>(&data.db.conn)?;
+    let group = find_group(&data.db.conn, &req.code)?;
 
     let new_user = user::NewUser {
         name: req.name,
-        group_id: groups[0].id,
+        token: hash_token(&req.token)?,
+        group_id: group.id,
     };
 
     let user_id = diesel::insert_into(users)
@@ -44,7 +72,7 @@ pub fn create(data: RequestData) -> RequestResult {
 }
 
 // user.auth
-pub fn auth(data: RequestData) -> RequestResult {
+pub fn auth(mut data: RequestData) -> RequestResult {
     use crate::model::schema::user_groups;
     use crate::model::schema::user_groups::dsl::*;
     use crate::model::schema::users;
@@ -56,7 +84,14 @@ pub fn auth(data: RequestData) -> RequestResult {
         token: String,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.positive("id", self.id.into());
+            v.not_empty("token", &self.token);
+        }
+    }
+
+    let req = data.parse::<Req>()?;
 
     let result = users
         .filter(users::id.eq(req.id))
@@ -64,18 +99,27 @@ pub fn auth(data: RequestData) -> RequestResult {
         .optional()?;
 
     if let Some(r) = result {
-        if r.token != req.token {
-            return Err(api::make_error(api::error::WRONG_USER_PASSWORD));
+        if !verify_token(&req.token, &r.token)? {
+            return Err(api::Failure::WrongPassword.into());
+        }
+
+        if !is_hashed(&r.token) {
+            diesel::update(users.filter(users::id.eq(r.id)))
+                .set(token.eq(hash_token(&req.token)?))
+                .execute(&data.db.conn)?;
         }
 
         let user_group = user_groups
             .filter(user_groups::id.eq(r.group_id))
             .first::<user_group::UserGroup>(&data.db.conn)?;
 
+        let session = api::session::create(&data.db.conn, r.id, data.client.user_agent.clone())?;
+
         Ok(Some(json!({ "code": user_group.code,
-             "name": r.name })))
+             "name": r.name,
+             "session": session })))
     } else {
-        Err(api::make_error(api::error::WRONG_USER_PASSWORD))
+        Err(api::Failure::WrongPassword.into())
     }
 }
 
@@ -83,49 +127,83 @@ pub fn auth(data: RequestData) -> RequestResult {
 pub fn get_one(data: RequestData) -> RequestResult {
     use crate::model::schema::user_groups;
     use crate::model::schema::user_groups::dsl::*;
+    use crate::model::schema::users;
     use crate::model::schema::users::dsl::*;
 
-    let params = data.params.unwrap();
-    let user_token = params["token"].as_str().unwrap();
+    #[derive(Deserialize)]
+    struct Req {
+        id: Option<i32>,
+    }
+
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            if let Some(i) = self.id {
+                v.positive("id", i.into());
+            }
+        }
+    }
+
+    let session_user_id = data.user_id();
+
+    let req_id = match data.params {
+        Some(p) => validation::parse::<Req>(p)?.id,
+        None => None,
+    };
+
+    let user_id = match req_id {
+        Some(i) => i,
+        None => session_user_id?,
+    };
 
     let user = users
-        .filter(token.eq(user_token))
-        .limit(1)
-        .load::<user::User>(&data.db.conn)?;
+        .filter(users::id.eq(user_id))
+        .first::<user::User>(&data.db.conn)?;
 
     let user_group = user_groups
-        .filter(user_groups::id.eq(user[0].group_id))
-        .limit(1)
-        .load::<user_group::UserGroup>(&data.db.conn)?;
+        .filter(user_groups::id.eq(user.group_id))
+        .first::<user_group::UserGroup>(&data.db.conn)?;
 
     let result = json!({
-        "id": user[0].id,
-        "name": user[0].name,
-        "code": user_group[0].code,
-        "create_ts": user[0].create_ts
+        "id": user.id,
+        "name": user.name,
+        "code": user_group.code,
+        "create_ts": user.create_ts
     });
 
     Ok(Some(result))
 }
 
 // user.update
-pub fn update(data: RequestData) -> RequestResult {
-    use crate::model::schema::user_groups::dsl::*;
+pub fn update(mut data: RequestData) -> RequestResult {
     use crate::model::schema::users;
     use crate::model::schema::users::dsl::*;
 
     #[derive(Deserialize)]
     struct Req {
-        id: i32,
+        id: Option<i32>,
         name: String,
         code: String,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            if let Some(i) = self.id {
+                v.positive("id", i.into());
+            }
+            validate_name(v, &self.name);
+            v.not_empty("code", &self.code);
+        }
+    }
 
-    let groups = user_groups
-        .filter(code.eq(req.code))
-        .first::<user_group::UserGroup>(&data.db.conn)?;
+    let session_user_id = data.user_id()?;
+    let req = data.parse::<Req>()?;
+    let user_id = req.id.unwrap_or(session_user_id);
+
+    if user_id != session_user_id || req.code != data.client.group.code {
+        data.require_permission("user.update.any")?;
+    }
+
+    let group = find_group(&data.db.conn, &req.code)?;
 
     #[derive(AsChangeset)]
     #[table_name = "users"]
@@ -137,11 +215,11 @@ pub fn update(data: RequestData) -> RequestResult {
 
     let update_user = UpdateUser {
         name: req.name,
-        group_id: groups.id,
+        group_id: group.id,
         update_ts: Utc::now().naive_utc(),
     };
 
-    diesel::update(users.filter(users::id.eq(req.id)))
+    diesel::update(users.filter(users::id.eq(user_id)))
         .set(&update_user)
         .execute(&data.db.conn)?;
 
@@ -149,20 +227,160 @@ pub fn update(data: RequestData) -> RequestResult {
 }
 
 // user.changePassword
-pub fn change_password(data: RequestData) -> RequestResult {
+pub fn change_password(mut data: RequestData) -> RequestResult {
     use crate::model::schema::users::dsl::*;
 
     #[derive(Deserialize)]
     struct Req {
-        id: i32,
+        current_token: String,
         token: String,
     }
 
-    let req = serde_json::from_value::<Req>(data.params.unwrap())?;
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.not_empty("current_token", &self.current_token);
+            validate_token(v, &self.token);
+        }
+    }
 
-    diesel::update(users.filter(id.eq(req.id)))
-        .set(token.eq(req.token))
-        .execute(&data.db.conn)?;
+    let user_id = data.user_id()?;
+    let req = data.parse::<Req>()?;
+
+    // A session alone is not enough, it could be stolen
+    let stored = data.client.user.as_ref().map(|u| u.token.as_str());
+    if !verify_token(&req.current_token, stored.unwrap_or_default())? {
+        return Err(api::Failure::WrongPassword.into());
+    }
+
+    // bcrypt is slow, so the hash is made before the transaction
+    let new_token = hash_token(&req.token)?;
+
+    data.db
+        .conn
+        .transaction::<_, Box<dyn std::error::Error>, _>(|| {
+            diesel::update(users.filter(id.eq(user_id)))
+                .set(token.eq(&new_token))
+                .execute(&data.db.conn)?;
+
+            // Other sessions could be opened with the old password
+            use crate::model::schema::sessions;
+            let current_session = data.client.session.as_ref().map_or(0, |s| s.id);
+            diesel::delete(
+                sessions::table.filter(
+                    sessions::user_id
+                        .eq(user_id)
+                        .and(sessions::id.ne(current_session)),
+                ),
+            )
+            .execute(&data.db.conn)?;
+
+            Ok(())
+        })?;
 
     Ok(None)
 }
+
+// user.logout
+pub fn logout(data: RequestData) -> RequestResult {
+    use crate::model::schema::sessions::dsl::*;
+
+    if let Some(session) = &data.client.session {
+        diesel::delete(sessions.filter(id.eq(session.id))).execute(&data.db.conn)?;
+    }
+
+    Ok(None)
+}
+
+// user.logoutAll
+pub fn logout_all(data: RequestData) -> RequestResult {
+    use crate::model::schema::sessions::dsl::*;
+
+    let session_user_id = data.user_id()?;
+
+    diesel::delete(sessions.filter(user_id.eq(session_user_id))).execute(&data.db.conn)?;
+
+    Ok(None)
+}
+
+// user.getSessions
+pub fn get_sessions(data: RequestData) -> RequestResult {
+    use crate::model::schema::sessions::dsl::*;
+
+    let session_user_id = data.user_id()?;
+
+    let list = sessions
+        .filter(
+            user_id
+                .eq(session_user_id)
+                .and(expire_ts.gt(Utc::now().naive_utc())),
+        )
+        .order(create_ts.desc())
+        .load::<session::Session>(&data.db.conn)?;
+
+    let current_id = data.client.session.as_ref().map(|s| s.id);
+
+    #[derive(Serialize)]
+    struct SessionResp {
+        #[serde(flatten)]
+        session: session::Session,
+        current: bool,
+    }
+
+    let resp = list
+        .into_iter()
+        .map(|s| SessionResp {
+            current: Some(s.id) == current_id,
+            session: s,
+        })
+        .collect::<Vec<SessionResp>>();
+
+    let result = serde_json::to_value(&resp)?;
+    Ok(Some(result))
+}
+
+fn hash_token(plain: &str) -> Result<String, bcrypt::BcryptError> {
+    bcrypt::hash(plain, bcrypt::DEFAULT_COST)
+}
+
+// Default values of the token column, such users have no password set
+const PLACEHOLDER_TOKENS: &[&str] = &["", "dummy"];
+
+// Tokens saved before hashing was introduced are stored as plain text
+// and are rehashed on the first successful authorization
+fn is_hashed(stored: &str) -> bool {
+    stored.starts_with("$2")
+}
+
+fn verify_token(plain: &str, stored: &str) -> Result<bool, bcrypt::BcryptError> {
+    if is_hashed(stored) {
+        bcrypt::verify(plain, stored)
+    } else if PLACEHOLDER_TOKENS.contains(&stored.trim()) {
+        Ok(false)
+    } else {
+        Ok(constant_time_eq(plain.as_bytes(), stored.as_bytes()))
+    }
+}
+
+// Time depends only on lengths, not on the position of the first difference
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Other groups are assigned only by those who may update any user
+fn is_default_group(group_code: &str) -> bool {
+    group_code == user_group::USER
+}
+
+// Groups are referenced by code in requests
+pub fn find_group(
+    conn: &db::TimedConnection,
+    group_code: &str,
+) -> Result<user_group::UserGroup, Box<dyn std::error::Error>> {
+    use crate::model::schema::user_groups::dsl::*;
+
+    user_groups
+        .filter(code.eq(group_code))
+        .first::<user_group::UserGroup>(conn)
+        .optional()?
+        .ok_or_else(|| validation::invalid("code", "unknown_group"))
+}
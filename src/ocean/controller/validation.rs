@@ -0,0 +1,241 @@
+use crate::api;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+
+// Checks of request parameters made after deserialization
+pub trait Validate {
+    fn validate(&self, _v: &mut Validator) {}
+}
+
+// Reason is a key of the validation messages in locale catalogs,
+// it is localized with its args when the response is made
+#[derive(Serialize)]
+pub struct FieldError {
+    field: String,
+    reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<serde_json::Value>,
+}
+
+// Collects all field errors of a request to report them at once
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(&mut self, valid: bool, field: &str, reason: &str) -> &mut Self {
+        self.check_args(valid, field, reason, None)
+    }
+
+    fn check_args(
+        &mut self,
+        valid: bool,
+        field: &str,
+        reason: &str,
+        args: Option<serde_json::Value>,
+    ) -> &mut Self {
+        if !valid {
+            self.errors.push(FieldError {
+                field: field.to_string(),
+                reason: reason.to_string(),
+                args,
+            });
+        }
+        self
+    }
+
+    pub fn not_empty(&mut self, field: &str, value: &str) -> &mut Self {
+        self.check(!value.trim().is_empty(), field, "not_empty")
+    }
+
+    pub fn max_length(&mut self, field: &str, value: &str, max: usize) -> &mut Self {
+        let args = json!({ "max": max });
+        self.check_args(
+            value.chars().count() <= max,
+            field,
+            "max_length",
+            Some(args),
+        )
+    }
+
+    pub fn max_bytes(&mut self, field: &str, value: &str, max: usize) -> &mut Self {
+        let args = json!({ "max": max });
+        self.check_args(value.len() <= max, field, "max_bytes", Some(args))
+    }
+
+    pub fn range(&mut self, field: &str, value: i64, min: i64, max: i64) -> &mut Self {
+        let args = json!({ "min": min, "max": max });
+        self.check_args(value >= min && value <= max, field, "range", Some(args))
+    }
+
+    pub fn positive(&mut self, field: &str, value: i64) -> &mut Self {
+        self.check(value > 0, field, "positive")
+    }
+
+    pub fn non_negative(&mut self, field: &str, value: i64) -> &mut Self {
+        self.check(value >= 0, field, "non_negative")
+    }
+
+    pub fn array(&mut self, field: &str, value: &serde_json::Value) -> &mut Self {
+        self.check(value.is_array(), field, "array")
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(api::Failure::Validation(serde_json::to_value(&self.errors)?).into())
+        }
+    }
+}
+
+// Error of a single field found after validation, e.g. an unknown reference
+pub fn invalid(field: &str, reason: &str) -> Box<dyn std::error::Error> {
+    let mut v = Validator::new();
+    v.check(false, field, reason);
+    v.finish().unwrap_err()
+}
+
+// Deserializes request parameters and validates them
+pub fn parse<T: DeserializeOwned + Validate>(
+    params: serde_json::Value,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let req = serde_json::from_value::<T>(params).map_err(|e| {
+        let mut v = Validator::new();
+        let args = json!({ "error": e.to_string() });
+        v.check_args(false, "params", "malformed", Some(args));
+        v.finish().unwrap_err()
+    })?;
+
+    let mut v = Validator::new();
+    req.validate(&mut v);
+    v.finish()?;
+
+    Ok(req)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::error::find_error;
+    use serde::Deserialize;
+
+    fn errors(v: Validator) -> serde_json::Value {
+        let err = v.finish().unwrap_err();
+        let err = find_error(err.as_ref()).unwrap();
+        assert_eq!(err.code(), api::error::INVALID_PARAMETER);
+        err.data().unwrap()
+    }
+
+    #[test]
+    fn valid_values_pass() {
+        let mut v = Validator::new();
+        v.not_empty("a", "x")
+            .max_length("b", "абв", 3)
+            .max_bytes("c", "abc", 3)
+            .range("d", 5, 1, 5)
+            .positive("e", 1)
+            .non_negative("f", 0)
+            .array("g", &json!([]));
+        assert!(v.finish().is_ok());
+    }
+
+    #[test]
+    fn all_errors_are_collected() {
+        let mut v = Validator::new();
+        v.not_empty("a", "  ")
+            .positive("b", 0)
+            .array("c", &json!({}));
+        assert_eq!(
+            errors(v),
+            json!([
+                { "field": "a", "reason": "not_empty" },
+                { "field": "b", "reason": "positive" },
+                { "field": "c", "reason": "array" },
+            ])
+        );
+    }
+
+    #[test]
+    fn length_counts_chars_and_bytes() {
+        let mut v = Validator::new();
+        v.max_length("name", "абв", 2).max_bytes("token", "абв", 5);
+        assert_eq!(
+            errors(v),
+            json!([
+                { "field": "name", "reason": "max_length", "args": { "max": 2 } },
+                { "field": "token", "reason": "max_bytes", "args": { "max": 5 } },
+            ])
+        );
+    }
+
+    #[test]
+    fn range_bounds() {
+        let mut v = Validator::new();
+        v.range("limit", 0, 1, 10).range("limit", 11, 1, 10);
+        let data = errors(v);
+        assert_eq!(data.as_array().unwrap().len(), 2);
+        assert_eq!(data[0]["args"], json!({ "min": 1, "max": 10 }));
+    }
+
+    #[test]
+    fn invalid_reports_a_single_field() {
+        let err = invalid("code", "unknown_group");
+        let err = find_error(err.as_ref()).unwrap();
+        assert_eq!(
+            err.data(),
+            Some(json!([{ "field": "code", "reason": "unknown_group" }]))
+        );
+    }
+
+    #[derive(Deserialize)]
+    struct Req {
+        id: i64,
+    }
+
+    impl Validate for Req {
+        fn validate(&self, v: &mut Validator) {
+            v.positive("id", self.id);
+        }
+    }
+
+    #[test]
+    fn parse_validates_after_deserialization() {
+        assert_eq!(parse::<Req>(json!({ "id": 1 })).unwrap().id, 1);
+
+        let err = parse::<Req>(json!({ "id": -1 })).err().unwrap();
+        let data = find_error(err.as_ref()).unwrap().data().unwrap();
+        assert_eq!(data[0]["reason"], "positive");
+
+        let err = parse::<Req>(json!({ "id": "x" })).err().unwrap();
+        let data = find_error(err.as_ref()).unwrap().data().unwrap();
+        assert_eq!(data[0]["field"], "params");
+        assert_eq!(data[0]["reason"], "malformed");
+        assert!(data[0]["args"]["error"].is_string());
+    }
+
+    #[test]
+    fn reasons_are_localized() {
+        let mut v = Validator::new();
+        v.max_length("name", "abc", 2);
+        let err = v.finish().unwrap_err();
+        let err = find_error(err.as_ref()).unwrap();
+
+        let en = api::Locale::from_accept_language(Some("en"));
+        assert_eq!(
+            err.localized_data(&en),
+            Some(json!([{ "field": "name", "reason": "must be at most 2 characters" }]))
+        );
+        let ru = api::Locale::default();
+        assert_eq!(
+            err.localized_data(&ru).unwrap()[0]["reason"],
+            "должно быть не длиннее 2 символов"
+        );
+    }
+}
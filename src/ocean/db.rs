@@ -1,27 +1,212 @@
 use crate::config;
-use diesel::pg::PgConnection;
-use diesel::prelude::*;
+use diesel::connection::{AnsiTransactionManager, Connection, SimpleConnection};
+use diesel::deserialize::{Queryable, QueryableByName};
+use diesel::pg::{Pg, PgConnection};
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::r2d2;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
+use diesel::sql_types::HasSqlType;
+use diesel::{ConnectionError, ConnectionResult, QueryResult};
+use diesel_migrations::MigrationConnection;
+use log::info;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+lazy_static! {
+    static ref POOL: PgPool = create_pool();
+    // Versions of migrations applied on startup
+    static ref MIGRATIONS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+}
 
 pub struct Db {
-    pub conn: PgConnection,
+    pub conn: TimedConnection,
 }
 
 impl Db {
-    pub fn new() -> Db {
-        let database_url = format!(
-            "postgres://{}:{}@localhost/{}",
-            config::CONFIG.postgres.username,
-            config::CONFIG.postgres.password,
-            config::CONFIG.postgres.database
-        );
-        let conn = PgConnection::establish(&database_url)
-            .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
-        Db { conn }
+    pub fn new() -> Result<Db, PoolError> {
+        Self::checkout(|| POOL.get())
+    }
+
+    // Waits for a free connection less than the configured timeout
+    pub fn with_timeout(timeout: Duration) -> Result<Db, PoolError> {
+        Self::checkout(|| POOL.get_timeout(timeout))
+    }
+
+    fn checkout(
+        get: impl FnOnce() -> Result<PooledConnection<ConnectionManager<PgConnection>>, PoolError>,
+    ) -> Result<Db, PoolError> {
+        let time = DbTime::default();
+        let conn = time.measure(get)?;
+        Ok(Db {
+            conn: TimedConnection { conn, time },
+        })
+    }
+
+    // Wait for the pool and time of queries, it can be read after the Db is dropped
+    pub fn time(&self) -> DbTime {
+        self.conn.time.clone()
+    }
+}
+
+// Total time of database work shared between threads
+#[derive(Clone, Default)]
+pub struct DbTime(Arc<AtomicU64>);
+
+impl DbTime {
+    pub fn get(&self) -> Duration {
+        Duration::from_micros(self.0.load(Ordering::Relaxed))
+    }
+
+    fn measure<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.0
+            .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+// Pooled connection which counts the time of every query. Transactions are
+// counted by their statements, so nothing is counted twice.
+pub struct TimedConnection {
+    conn: PooledConnection<ConnectionManager<PgConnection>>,
+    time: DbTime,
+}
+
+impl SimpleConnection for TimedConnection {
+    fn batch_execute(&self, query: &str) -> QueryResult<()> {
+        self.time.measure(|| self.conn.batch_execute(query))
+    }
+}
+
+impl Connection for TimedConnection {
+    type Backend = Pg;
+    type TransactionManager = AnsiTransactionManager;
+
+    fn establish(_: &str) -> ConnectionResult<Self> {
+        Err(ConnectionError::BadConnection(String::from(
+            "Cannot directly establish a pooled connection",
+        )))
+    }
+
+    fn execute(&self, query: &str) -> QueryResult<usize> {
+        self.time.measure(|| self.conn.execute(query))
     }
+
+    fn query_by_index<T, U>(&self, source: T) -> QueryResult<Vec<U>>
+    where
+        T: AsQuery,
+        T::Query: QueryFragment<Pg> + QueryId,
+        Pg: HasSqlType<T::SqlType>,
+        U: Queryable<T::SqlType, Pg>,
+    {
+        self.time.measure(|| self.conn.query_by_index(source))
+    }
+
+    fn query_by_name<T, U>(&self, source: &T) -> QueryResult<Vec<U>>
+    where
+        T: QueryFragment<Pg> + QueryId,
+        U: QueryableByName<Pg>,
+    {
+        self.time.measure(|| self.conn.query_by_name(source))
+    }
+
+    fn execute_returning_count<T>(&self, source: &T) -> QueryResult<usize>
+    where
+        T: QueryFragment<Pg> + QueryId,
+    {
+        self.time
+            .measure(|| self.conn.execute_returning_count(source))
+    }
+
+    fn transaction_manager(&self) -> &Self::TransactionManager {
+        self.conn.transaction_manager()
+    }
+}
+
+pub fn pool_state() -> r2d2::State {
+    POOL.state()
+}
+
+// Remembers migrations applied after embedded ones are run on startup
+pub fn set_migrated(conn: &TimedConnection) -> QueryResult<()> {
+    let versions = conn.previously_run_migration_versions()?;
+    *MIGRATIONS.lock().unwrap() = Some(versions.into_iter().collect());
+    Ok(())
+}
+
+// Migrations applied on startup which are missing in the database now,
+// e.g. after it is restored from a backup or switched to another server
+pub fn pending_migrations(conn: &TimedConnection) -> QueryResult<Option<Vec<String>>> {
+    let expected = match &*MIGRATIONS.lock().unwrap() {
+        Some(m) => m.clone(),
+        None => return Ok(None),
+    };
+
+    let applied = conn.previously_run_migration_versions()?;
+    Ok(Some(
+        expected
+            .into_iter()
+            .filter(|v| !applied.contains(v))
+            .collect(),
+    ))
+}
+
+fn create_pool() -> PgPool {
+    let config = config::get();
+    let postgres = &config.postgres;
+    let database_url =
+        database_url(postgres).unwrap_or_else(|e| panic!("Invalid database URL: {}", e));
+
+    info!("Database: {}", mask_password(&database_url));
+
+    let manager = ConnectionManager::<PgConnection>::new(database_url.into_string());
+
+    // Connections are established lazily, so errors are reported on checkout
+    Pool::builder()
+        .max_size(postgres.pool_size)
+        .connection_timeout(Duration::from_secs(postgres.connection_timeout))
+        .build_unchecked(manager)
+}
+
+// DATABASE_URL environment variable takes precedence over `url` from config,
+// which in its turn replaces separate connection fields
+fn database_url(postgres: &config::Postgres) -> Result<Url, url::ParseError> {
+    let mut url = if let Ok(u) = env::var("DATABASE_URL") {
+        Url::parse(&u)?
+    } else if let Some(u) = &postgres.url {
+        Url::parse(u)?
+    } else {
+        let mut u = Url::parse("postgres://localhost")?;
+        u.set_host(Some(&postgres.host))?;
+        // Can't fail for URL with host
+        let _ = u.set_port(Some(postgres.port));
+        let _ = u.set_username(&postgres.username);
+        if !postgres.password.is_empty() {
+            let _ = u.set_password(Some(&postgres.password));
+        }
+        u.set_path(&format!("/{}", postgres.database));
+        u
+    };
+
+    if let Some(mode) = &postgres.ssl_mode {
+        if !url.query_pairs().any(|(k, _)| k == "sslmode") {
+            url.query_pairs_mut().append_pair("sslmode", mode);
+        }
+    }
+
+    Ok(url)
 }
 
-impl Default for Db {
-    fn default() -> Self {
-        Self::new()
+fn mask_password(url: &Url) -> Url {
+    let mut masked = url.clone();
+    if masked.password().is_some() {
+        let _ = masked.set_password(Some("***"));
     }
+    masked
 }
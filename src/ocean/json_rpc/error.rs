@@ -1,16 +1,28 @@
 use crate::api;
 use serde::Serialize;
+use serde_json::json;
+
+// Codes reserved by JSON-RPC 2.0 specification
+pub const PARSE_ERROR: api::error::ErrorCode = -32700;
+pub const INVALID_REQUEST: api::error::ErrorCode = -32600;
+pub const METHOD_NOT_FOUND: api::error::ErrorCode = -32601;
+pub const INVALID_PARAMS: api::error::ErrorCode = -32602;
+pub const INTERNAL_ERROR: api::error::ErrorCode = -32603;
 
 #[derive(Serialize)]
 pub struct Error {
     pub code: api::error::ErrorCode,
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub data: Option<String>,
+    pub data: Option<serde_json::Value>,
 }
 
 impl Error {
-    pub fn new(code: api::error::ErrorCode, message: String, data: Option<String>) -> Error {
+    pub fn new(
+        code: api::error::ErrorCode,
+        message: String,
+        data: Option<serde_json::Value>,
+    ) -> Error {
         Error {
             code,
             message,
@@ -18,7 +30,88 @@ impl Error {
         }
     }
 
-    pub fn from_api_error(err: &api::error::Error) -> Error {
-        Self::new(err.code(), err.message(), err.data())
+    // Errors with standard equivalents keep the API code in data,
+    // e.g. {"code": 6, "details": [...]}, so they are still distinguished
+    pub fn from_api_error(err: &api::error::Error, locale: &api::Locale) -> Error {
+        let message = err.localized_message(locale);
+
+        match standard_code(err.code()) {
+            Some(code) => {
+                let mut data = json!({ "code": err.code() });
+                if let Some(details) = err.localized_data(locale) {
+                    data["details"] = details;
+                }
+                Self::new(code, message, Some(data))
+            }
+            None => Self::new(err.code(), message, err.localized_data(locale)),
+        }
+    }
+}
+
+// Common API errors have standard equivalents, the rest are application defined
+fn standard_code(code: api::error::ErrorCode) -> Option<api::error::ErrorCode> {
+    match code {
+        api::error::PARSE_ERROR => Some(PARSE_ERROR),
+        api::error::INVALID_REQUEST => Some(INVALID_REQUEST),
+        api::error::CONTROLLER_NOT_FOUND | api::error::METHOD_NOT_FOUND => Some(METHOD_NOT_FOUND),
+        api::error::PARAMETER_NOT_FOUND | api::error::INVALID_PARAMETER => Some(INVALID_PARAMS),
+        api::error::INTERNAL_SERVER_ERROR => Some(INTERNAL_ERROR),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(code: api::error::ErrorCode, data: Option<serde_json::Value>) -> Error {
+        let err = api::Error::new(code, data);
+        Error::from_api_error(&err, &api::Locale::default())
+    }
+
+    #[test]
+    fn common_codes_have_standard_equivalents() {
+        let cases = [
+            (api::error::PARSE_ERROR, PARSE_ERROR),
+            (api::error::INVALID_REQUEST, INVALID_REQUEST),
+            (api::error::CONTROLLER_NOT_FOUND, METHOD_NOT_FOUND),
+            (api::error::METHOD_NOT_FOUND, METHOD_NOT_FOUND),
+            (api::error::PARAMETER_NOT_FOUND, INVALID_PARAMS),
+            (api::error::INVALID_PARAMETER, INVALID_PARAMS),
+            (api::error::INTERNAL_SERVER_ERROR, INTERNAL_ERROR),
+        ];
+        for &(api_code, code) in &cases {
+            let err = convert(api_code, None);
+            assert_eq!(err.code, code);
+            assert_eq!(err.data, Some(json!({ "code": api_code })));
+        }
+    }
+
+    #[test]
+    fn api_code_and_details_are_kept_in_data() {
+        let err = convert(api::error::METHOD_NOT_FOUND, Some(json!("a.b")));
+        assert_eq!(err.code, METHOD_NOT_FOUND);
+        assert_eq!(
+            err.data,
+            Some(json!({ "code": api::error::METHOD_NOT_FOUND, "details": "a.b" }))
+        );
+    }
+
+    #[test]
+    fn application_codes_are_unchanged() {
+        let err = convert(api::error::NOT_FOUND, Some(json!("mandela")));
+        assert_eq!(err.code, api::error::NOT_FOUND);
+        assert_eq!(err.data, Some(json!("mandela")));
+
+        let err = convert(api::error::NOT_AUTHORIZED, None);
+        assert_eq!(err.code, api::error::NOT_AUTHORIZED);
+        assert_eq!(err.data, None);
+    }
+
+    #[test]
+    fn message_follows_locale() {
+        let err = api::Error::new(api::error::NOT_FOUND, None);
+        let en = api::Locale::from_accept_language(Some("en"));
+        assert_eq!(Error::from_api_error(&err, &en).message, "Not found");
     }
 }
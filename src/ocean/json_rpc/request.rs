@@ -1,8 +1,89 @@
 use serde::Deserialize;
+use serde::Deserializer;
+
+pub const VERSION: &str = "2.0";
 
 #[derive(Deserialize)]
 pub struct Request {
-    pub id: Option<String>,
+    pub jsonrpc: Option<String>,
+    // Absent id means notification, so explicit null must be kept as Some(Null)
+    #[serde(default, deserialize_with = "deserialize_id")]
+    pub id: Option<serde_json::Value>,
     pub method: String,
     pub params: Option<serde_json::Value>,
+    // Language of error messages, takes precedence over Accept-Language header
+    pub lang: Option<String>,
+}
+
+impl Request {
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let valid_id = match &self.id {
+            Some(i) => i.is_string() || i.is_number() || i.is_null(),
+            None => true,
+        };
+
+        // Clients written before JSON-RPC 2.0 support don't send the version
+        let valid_version = match &self.jsonrpc {
+            Some(v) => v == VERSION,
+            None => true,
+        };
+
+        valid_version && valid_id
+    }
+}
+
+fn deserialize_id<'de, D>(deserializer: D) -> Result<Option<serde_json::Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    serde_json::Value::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(value: serde_json::Value) -> Request {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn absent_id_is_notification() {
+        let req = parse(json!({ "jsonrpc": "2.0", "method": "a" }));
+        assert!(req.is_notification());
+        assert!(req.is_valid());
+    }
+
+    #[test]
+    fn null_id_is_kept() {
+        let req = parse(json!({ "jsonrpc": "2.0", "method": "a", "id": null }));
+        assert_eq!(req.id, Some(serde_json::Value::Null));
+        assert!(!req.is_notification());
+        assert!(req.is_valid());
+    }
+
+    #[test]
+    fn numbers_and_strings_are_valid_ids() {
+        assert!(parse(json!({ "method": "a", "id": 1 })).is_valid());
+        assert!(parse(json!({ "method": "a", "id": 1.5 })).is_valid());
+        assert!(parse(json!({ "method": "a", "id": "x" })).is_valid());
+    }
+
+    #[test]
+    fn objects_and_arrays_are_invalid_ids() {
+        assert!(!parse(json!({ "method": "a", "id": {} })).is_valid());
+        assert!(!parse(json!({ "method": "a", "id": [1] })).is_valid());
+        assert!(!parse(json!({ "method": "a", "id": true })).is_valid());
+    }
+
+    #[test]
+    fn version_is_optional_but_checked() {
+        assert!(parse(json!({ "method": "a", "id": 1 })).is_valid());
+        assert!(!parse(json!({ "jsonrpc": "1.0", "method": "a", "id": 1 })).is_valid());
+    }
 }
@@ -1,23 +1,36 @@
+use crate::json_rpc::request::VERSION;
 use crate::json_rpc::Error;
 use serde::Serialize;
 
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Payload {
+    Result(serde_json::Value),
+    Error(Error),
+}
+
 #[derive(Serialize)]
 pub struct Response {
-    pub id: String,
-    pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub result: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<Error>,
+    pub jsonrpc: &'static str,
+    #[serde(flatten)]
+    pub payload: Payload,
+    pub id: serde_json::Value,
 }
 
-impl Default for Response {
-    fn default() -> Self {
+impl Response {
+    pub fn result(id: serde_json::Value, result: Option<serde_json::Value>) -> Self {
+        Response {
+            jsonrpc: VERSION,
+            payload: Payload::Result(result.unwrap_or(serde_json::Value::Null)),
+            id,
+        }
+    }
+
+    pub fn error(id: serde_json::Value, error: Error) -> Self {
         Response {
-            id: "".to_string(),
-            method: "".to_string(),
-            result: None,
-            error: None,
+            jsonrpc: VERSION,
+            payload: Payload::Error(error),
+            id,
         }
     }
 }
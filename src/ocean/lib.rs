@@ -10,5 +10,7 @@ pub mod config;
 pub mod controller;
 pub mod db;
 pub mod json_rpc;
+pub mod logger;
+pub mod metrics;
 pub mod model;
 pub mod telegram_bot;
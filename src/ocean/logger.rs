@@ -0,0 +1,100 @@
+use crate::config;
+use chrono::prelude::*;
+use env_logger::fmt::Formatter;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+use std::io;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Entries of this target carry a JSON object of fields as the message
+pub const FIELDS_TARGET: &str = "ocean::fields";
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+// Records are checked against the level set at runtime, since some crates log
+// through adapters which skip the level check of log macros
+struct Logger {
+    text: env_logger::Logger,
+    json: env_logger::Logger,
+}
+
+impl Logger {
+    fn current(&self) -> &env_logger::Logger {
+        if is_json() {
+            &self.json
+        } else {
+            &self.text
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level() && self.current().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.current().log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.current().flush();
+    }
+}
+
+// The level is limited by config, RUST_LOG may still filter modules
+pub fn init() {
+    let builder = || {
+        let mut b = env_logger::Builder::new();
+        b.filter_level(LevelFilter::Trace)
+            .parse_filters(&std::env::var("RUST_LOG").unwrap_or_default());
+        b
+    };
+
+    let logger = Logger {
+        text: builder().format_timestamp(None).build(),
+        json: builder().format(format_json).build(),
+    };
+
+    log::set_boxed_logger(Box::new(logger)).unwrap();
+    log::set_max_level(LevelFilter::Info);
+}
+
+// Applies log settings of the config, also on reload
+pub fn configure(log: &config::Log) {
+    log::set_max_level(log.level_filter());
+    JSON.store(log.format == config::LogFormat::Json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+fn format_json(buf: &mut Formatter, record: &Record) -> io::Result<()> {
+    let mut entry = json!({
+        "ts": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "level": record.level().to_string(),
+        "target": record.target(),
+    });
+
+    let message = record.args().to_string();
+    let fields = if record.target() == FIELDS_TARGET {
+        serde_json::from_str::<serde_json::Value>(&message).ok()
+    } else {
+        None
+    };
+
+    match fields {
+        Some(serde_json::Value::Object(map)) => {
+            for (k, v) in map {
+                entry[k] = v;
+            }
+        }
+        _ => entry["message"] = serde_json::Value::from(message),
+    }
+
+    writeln!(buf, "{}", entry)
+}
@@ -0,0 +1,117 @@
+use crate::db;
+use diesel::prelude::*;
+use log::error;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+// Label of calls to methods which do not exist, to keep the number of series bounded
+pub const UNKNOWN_METHOD: &str = "unknown";
+
+const DB_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+    static ref CALLS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("ocean_rpc_calls_total", "JSON-RPC calls by method"),
+        &["method"],
+    ));
+    static ref ERRORS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new(
+            "ocean_rpc_errors_total",
+            "JSON-RPC errors by method and code"
+        ),
+        &["method", "code"],
+    ));
+    static ref REQUEST_ERRORS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new(
+            "ocean_rpc_request_errors_total",
+            "JSON-RPC requests failed before calls to methods, by code"
+        ),
+        &["code"],
+    ));
+    static ref LATENCY: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new("ocean_rpc_latency_seconds", "JSON-RPC call latency"),
+        &["method"],
+    ));
+    static ref POOL_CONNECTIONS: IntGaugeVec = register(IntGaugeVec::new(
+        Opts::new(
+            "ocean_db_pool_connections",
+            "Database pool connections by state"
+        ),
+        &["state"],
+    ));
+    static ref TELEGRAM_MESSAGES: IntCounterVec = register(IntCounterVec::new(
+        Opts::new(
+            "ocean_telegram_messages_total",
+            "Telegram messages by result"
+        ),
+        &["result"],
+    ));
+    static ref TELEGRAM_SUBSCRIBERS: IntGauge = register(IntGauge::new(
+        "ocean_telegram_subscribers",
+        "Chats subscribed to the Telegram bot",
+    ));
+}
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(
+    collector: prometheus::Result<T>,
+) -> T {
+    let collector = collector.unwrap();
+    REGISTRY.register(Box::new(collector.clone())).unwrap();
+    collector
+}
+
+pub fn observe_call(method: &str, error_code: Option<i32>, latency: std::time::Duration) {
+    CALLS.with_label_values(&[method]).inc();
+    LATENCY
+        .with_label_values(&[method])
+        .observe(latency.as_secs_f64());
+
+    if let Some(code) = error_code {
+        ERRORS.with_label_values(&[method, &code.to_string()]).inc();
+    }
+}
+
+// Parse errors, invalid requests and batches, panics of request tasks
+pub fn observe_request_error(code: i32) {
+    REQUEST_ERRORS.with_label_values(&[&code.to_string()]).inc();
+}
+
+pub fn observe_telegram_message(sent: bool) {
+    let result = if sent { "success" } else { "failure" };
+    TELEGRAM_MESSAGES.with_label_values(&[result]).inc();
+}
+
+// Gauges are updated on scrape, so this does blocking database calls
+pub fn gather() -> String {
+    let pool = db::pool_state();
+    POOL_CONNECTIONS
+        .with_label_values(&["idle"])
+        .set(pool.idle_connections.into());
+    POOL_CONNECTIONS
+        .with_label_values(&["active"])
+        .set((pool.connections - pool.idle_connections).into());
+
+    match count_subscribers() {
+        Ok(c) => TELEGRAM_SUBSCRIBERS.set(c),
+        Err(e) => error!("Metrics error: {}", e),
+    }
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+        error!("Metrics error: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+fn count_subscribers() -> Result<i64, Box<dyn std::error::Error>> {
+    use crate::model::schema::telegram_chats::dsl::*;
+
+    // A scrape must not wait for the pool as long as requests do
+    let db = db::Db::with_timeout(DB_TIMEOUT)?;
+    Ok(telegram_chats
+        .select(diesel::dsl::count_star())
+        .first(&db.conn)?)
+}
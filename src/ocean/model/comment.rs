@@ -1,6 +1,5 @@
 use crate::model::schema::comments;
 use chrono::NaiveDateTime;
-use serde::Deserialize;
 use serde::Serialize;
 
 #[derive(Queryable, Serialize)]
@@ -13,7 +12,7 @@ pub struct Comment {
     pub update_ts: NaiveDateTime,
 }
 
-#[derive(Insertable, Deserialize)]
+#[derive(Insertable)]
 #[table_name = "comments"]
 pub struct NewComment {
     pub mandela_id: i32,
@@ -1,5 +1,36 @@
 use crate::model::schema::mandels;
+use crate::model::variant::Variant;
 use chrono::NaiveDateTime;
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+// Simple mandela has a title, complex one consists of what, before and after
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "Variant")]
+pub enum TitleMode {
+    Simple = 0,
+    Complex = 1,
+}
+
+impl TryFrom<Variant> for TitleMode {
+    type Error = String;
+
+    fn try_from(v: Variant) -> Result<Self, Self::Error> {
+        v.resolve(
+            "title_mode",
+            &[
+                (0, "simple", TitleMode::Simple),
+                (1, "complex", TitleMode::Complex),
+            ],
+        )
+    }
+}
+
+impl From<TitleMode> for i32 {
+    fn from(mode: TitleMode) -> Self {
+        mode as i32
+    }
+}
 
 #[derive(Insertable)]
 #[table_name = "mandels"]
@@ -28,6 +59,5 @@ pub struct UpdateMandela {
     pub images: serde_json::Value,
     pub videos: serde_json::Value,
     pub links: serde_json::Value,
-    pub user_id: i32,
     pub update_ts: NaiveDateTime,
 }
@@ -1,5 +1,7 @@
 pub mod comment;
 pub mod mandela;
 pub mod schema;
+pub mod session;
 pub mod user;
 pub mod user_group;
+pub mod variant;
@@ -44,6 +44,25 @@ table! {
     }
 }
 
+table! {
+    permissions (id) {
+        id -> Int4,
+        group_id -> Int4,
+        action -> Text,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_hash -> Text,
+        user_agent -> Nullable<Text>,
+        create_ts -> Timestamptz,
+        expire_ts -> Timestamptz,
+    }
+}
+
 table! {
     telegram_chats (id) {
         id -> Int4,
@@ -95,6 +114,8 @@ joinable!(comments -> users (user_id));
 joinable!(mandels -> users (user_id));
 joinable!(marks -> mandels (mandela_id));
 joinable!(marks -> users (user_id));
+joinable!(permissions -> user_groups (group_id));
+joinable!(sessions -> users (user_id));
 joinable!(users -> user_groups (group_id));
 joinable!(votes -> mandels (mandela_id));
 joinable!(votes -> users (user_id));
@@ -104,6 +125,8 @@ allow_tables_to_appear_in_same_query!(
     comments,
     mandels,
     marks,
+    permissions,
+    sessions,
     telegram_chats,
     user_groups,
     users,
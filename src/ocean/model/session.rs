@@ -0,0 +1,23 @@
+use crate::model::schema::sessions;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+#[derive(Queryable, Serialize)]
+pub struct Session {
+    pub id: i32,
+    pub user_id: i32,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub user_agent: Option<String>,
+    pub create_ts: NaiveDateTime,
+    pub expire_ts: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "sessions"]
+pub struct NewSession {
+    pub user_id: i32,
+    pub token_hash: String,
+    pub user_agent: Option<String>,
+    pub expire_ts: NaiveDateTime,
+}
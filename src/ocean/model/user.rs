@@ -16,5 +16,6 @@ pub struct User {
 #[table_name = "users"]
 pub struct NewUser {
     pub name: Option<String>,
+    pub token: String,
     pub group_id: i32,
 }
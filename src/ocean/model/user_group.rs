@@ -1,5 +1,9 @@
 use serde::Serialize;
 
+pub const ADMIN: &str = "admin";
+pub const GUEST: &str = "guest";
+pub const USER: &str = "user";
+
 #[derive(Queryable, Serialize)]
 pub struct UserGroup {
     pub id: i32,
@@ -0,0 +1,93 @@
+use serde::Deserialize;
+use std::fmt;
+
+// Enum values are accepted in requests as legacy numbers or as names
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Variant {
+    Number(i64),
+    Name(String),
+}
+
+impl Variant {
+    // Finds a value by its number or name in the list of (number, name, value)
+    pub fn resolve<T: Copy>(&self, kind: &str, values: &[(i64, &str, T)]) -> Result<T, String> {
+        let found = values.iter().find(|(number, name, _)| match self {
+            Variant::Number(n) => n == number,
+            Variant::Name(s) => s == name,
+        });
+
+        match found {
+            Some((_, _, value)) => Ok(*value),
+            None => {
+                let expected = values
+                    .iter()
+                    .map(|(number, name, _)| format!("{} ({})", name, number))
+                    .collect::<Vec<_>>();
+                Err(format!(
+                    "unknown {} {}, expected one of: {}",
+                    kind,
+                    self,
+                    expected.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Variant::Number(n) => write!(f, "{}", n),
+            Variant::Name(s) => write!(f, "\"{}\"", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MODES: &[(i64, &str, char)] = &[(0, "simple", 's'), (1, "complex", 'c')];
+
+    fn resolve(value: serde_json::Value) -> Result<char, String> {
+        serde_json::from_value::<Variant>(value)
+            .map_err(|e| e.to_string())?
+            .resolve("mode", MODES)
+    }
+
+    #[test]
+    fn numbers_and_names_are_resolved() {
+        assert_eq!(resolve(json!(0)), Ok('s'));
+        assert_eq!(resolve(json!(1)), Ok('c'));
+        assert_eq!(resolve(json!("simple")), Ok('s'));
+        assert_eq!(resolve(json!("complex")), Ok('c'));
+    }
+
+    #[test]
+    fn unknown_values_list_expected_ones() {
+        let expected = "expected one of: simple (0), complex (1)";
+        assert_eq!(
+            resolve(json!(2)),
+            Err(format!("unknown mode 2, {}", expected))
+        );
+        assert_eq!(
+            resolve(json!("Simple")),
+            Err(format!("unknown mode \"Simple\", {}", expected))
+        );
+        assert_eq!(
+            resolve(json!("0")),
+            Err(format!("unknown mode \"0\", {}", expected))
+        );
+        assert!(resolve(json!(-1)).is_err());
+    }
+
+    #[test]
+    fn other_json_types_are_rejected() {
+        assert!(resolve(json!(1.0)).is_err());
+        assert!(resolve(json!(null)).is_err());
+        assert!(resolve(json!(true)).is_err());
+        assert!(resolve(json!([0])).is_err());
+    }
+}
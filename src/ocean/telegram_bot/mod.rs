@@ -1,37 +1,111 @@
 use crate::config;
 use crate::db;
+use crate::metrics;
 use chrono;
 use diesel::prelude::*;
 use log::error;
 use reqwest;
-use timer;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
 
 pub mod api;
 
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Unix time of the last poll of updates, the time of start before the first one
+static LAST_POLL: AtomicI64 = AtomicI64::new(0);
+
+lazy_static! {
+    // Number of threads sending messages to subscribers
+    static ref SENDING: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+}
+
+// Polls updates until dropped, the current batch of updates is finished on drop
 pub struct TelegramBot {
-    _guard: timer::Guard,
-    _timer: timer::Timer,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
 }
 
 impl TelegramBot {
     pub fn new() -> Self {
-        let timer = timer::Timer::new();
-        let guard = timer.schedule_repeating(
-            chrono::Duration::seconds(config::CONFIG.telegram_bot.interval),
-            move || {
+        LAST_POLL.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+
+        let interval = Duration::from_secs(config::get().telegram_bot.interval as u64);
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let thread = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
                 get_new_users();
-            },
-        );
+            }
+        });
 
         Self {
-            _guard: guard,
-            _timer: timer,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for TelegramBot {
+    fn drop(&mut self) {
+        self.stop.take();
+
+        if let Some(t) = self.thread.take() {
+            if t.join().is_err() {
+                error!("Telegram bot thread panicked");
+            }
         }
     }
 }
 
+// Waits for messages being sent to subscribers, returns false on timeout
+pub fn flush(timeout: Duration) -> bool {
+    let (count, done) = &*SENDING;
+    let count = count.lock().unwrap();
+    let (_count, res) = done.wait_timeout_while(count, timeout, |c| *c > 0).unwrap();
+    !res.timed_out()
+}
+
+// Counts a sending thread until it is finished or panicked
+struct Sending;
+
+impl Sending {
+    fn start() -> Self {
+        *SENDING.0.lock().unwrap() += 1;
+        Sending
+    }
+}
+
+impl Drop for Sending {
+    fn drop(&mut self) {
+        let (count, done) = &*SENDING;
+        *count.lock().unwrap() -= 1;
+        done.notify_all();
+    }
+}
+
+pub fn last_poll() -> Option<chrono::DateTime<chrono::Utc>> {
+    use chrono::TimeZone;
+
+    match LAST_POLL.load(Ordering::Relaxed) {
+        0 => None,
+        t => Some(chrono::Utc.timestamp(t, 0)),
+    }
+}
+
 fn get_new_users() {
-    let db = db::Db::new();
+    LAST_POLL.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+
+    let db = match db::Db::new() {
+        Ok(d) => d,
+        Err(e) => {
+            error!("Database pool error: {}", e);
+            return;
+        }
+    };
+
     let mut offset = get_offset(&db) + 1;
     let params = api::GetUpdatesParams { offset };
     let res = send_request("getUpdates", serde_json::to_value(params).unwrap());
@@ -141,13 +215,14 @@ fn send_message(chat_id: i32, text: String) {
         text,
         parse_mode: Some("HTML".into()),
     };
-    send_request("sendMessage", serde_json::to_value(params).unwrap());
+    let res = send_request("sendMessage", serde_json::to_value(params).unwrap());
+    metrics::observe_telegram_message(res != serde_json::Value::Null);
 }
 
 pub fn send_message_to_all(text: &String, db: &db::Db) {
     use crate::model::schema::telegram_chats::dsl::*;
 
-    if !config::CONFIG.telegram_bot.enabled {
+    if !config::get().telegram_bot.enabled {
         return;
     }
 
@@ -157,9 +232,10 @@ pub fn send_message_to_all(text: &String, db: &db::Db) {
         .unwrap();
 
     let t = text.clone();
+    let sending = Sending::start();
 
-    use std::thread;
     thread::spawn(move || {
+        let _sending = sending;
         for user_chat_id in chat_ids {
             send_message(user_chat_id, t.clone());
         }
@@ -184,7 +260,9 @@ async fn send(
     url: String,
     params: serde_json::Value,
 ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
 
     let resp = client
         .post(&url)
@@ -203,9 +281,6 @@ async fn send(
 }
 
 fn make_url(method: &str) -> String {
-    config::CONFIG.telegram_bot.url.clone()
-        + "/bot"
-        + &config::CONFIG.telegram_bot.token
-        + "/"
-        + method
+    let bot = &config::get().telegram_bot;
+    bot.url.clone() + "/bot" + &bot.token + "/" + method
 }